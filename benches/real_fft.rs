@@ -0,0 +1,74 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use voiche::{
+    fft::Fft,
+    num_complex::Complex,
+    pitch_shift::PitchShiftProcessor,
+    spectral::{SpectralDriver, SpectralProcessor},
+    windows,
+};
+
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+
+fn signal() -> Vec<f32> {
+    (0..WINDOW_SIZE).map(|i| (i as f32 * 0.05).sin()).collect()
+}
+
+fn fft(c: &mut Criterion) {
+    let fft = Fft::new(WINDOW_SIZE);
+    let buf = signal();
+    let mut scratch = vec![Complex::from(0.0); fft.scratch_len()];
+
+    let mut spectrum = vec![Complex::from(0.0); WINDOW_SIZE];
+    c.bench_function("complex fft round trip", |b| {
+        b.iter(|| {
+            for (x, &y) in spectrum.iter_mut().zip(&buf) {
+                *x = Complex::from(y);
+            }
+            fft.forward_with_scratch(&mut spectrum, &mut scratch);
+            fft.inverse_with_scratch(&mut spectrum, &mut scratch);
+            black_box(&spectrum);
+        })
+    });
+
+    let mut buffer = vec![0.0; WINDOW_SIZE];
+    let mut half_spectrum = vec![Complex::from(0.0); fft.half_len()];
+    c.bench_function("real fft round trip", |b| {
+        b.iter(|| {
+            buffer.copy_from_slice(&buf);
+            fft.forward_real_with_scratch(&mut buffer, &mut half_spectrum, &mut scratch);
+            fft.inverse_real_with_scratch(&mut half_spectrum, &mut buffer, &mut scratch);
+            black_box(&buffer);
+        })
+    });
+}
+
+fn driver(c: &mut Criterion) {
+    let buf = signal();
+    let mut output = vec![0.0; WINDOW_SIZE];
+
+    let mut driver = SpectralDriver::new(
+        windows::hann_window(WINDOW_SIZE),
+        windows::hann_window(WINDOW_SIZE),
+        SLIDE_SIZE,
+        PitchShiftProcessor::new(WINDOW_SIZE, SLIDE_SIZE, 1.2),
+    );
+    c.bench_function("pitch shift on the half spectrum", |b| {
+        b.iter(|| driver.process(&buf, &mut output))
+    });
+
+    // A closure processes the full spectrum.
+    let mut processor = PitchShiftProcessor::new(WINDOW_SIZE, SLIDE_SIZE, 1.2);
+    let mut driver = SpectralDriver::new(
+        windows::hann_window(WINDOW_SIZE),
+        windows::hann_window(WINDOW_SIZE),
+        SLIDE_SIZE,
+        move |spectrum: &mut [Complex<f32>]| processor.process(spectrum),
+    );
+    c.bench_function("pitch shift on the full spectrum", |b| {
+        b.iter(|| driver.process(&buf, &mut output))
+    });
+}
+
+criterion_group!(benches, fft, driver);
+criterion_main!(benches);
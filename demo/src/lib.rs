@@ -1,12 +1,14 @@
 use std::sync::{Arc, Mutex};
 
-use voiche::{api, fft::Fft, transform::Transformer, windows};
+use voiche::{
+    spectral::SpectralDriver, transform::RingTransformer, voice_change::VoiceChange, windows,
+};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
 pub struct Processor {
-    transformer: Transformer<f32, Box<dyn FnMut(&[f32]) -> Vec<f32>>>,
-    params: Arc<Mutex<(usize, f32, f32)>>,
+    transformer: RingTransformer<f32, Box<dyn FnMut(&[f32], &mut [f32])>>,
+    driver: Arc<Mutex<SpectralDriver<f32, VoiceChange<f32>>>>,
 }
 
 #[wasm_bindgen]
@@ -16,40 +18,22 @@ impl Processor {
         let window_size = 1024;
         let slide_size = window_size / 4;
         let pre_window = windows::hann_window(window_size);
-        let post_window = windows::trapezoid_window(window_size, slide_size);
-        let params = Arc::new(Mutex::new((window_size / 8, 1.0, 1.0)));
+        let post_window = windows::trapezoid_window(window_size, window_size - slide_size);
+        let driver = Arc::new(Mutex::new(SpectralDriver::new(
+            pre_window,
+            post_window,
+            slide_size,
+            VoiceChange::new(window_size, slide_size, window_size / 8, 1.0, 1.0),
+        )));
 
         let process = {
-            let fft = Fft::new(window_size);
-            let mut pitch_shift = voiche::pitch_shift::pitch_shifter(window_size);
-            let params = params.clone();
-
-            move |buf: &[f32]| {
-                api::retouch_spectrum(
-                    &fft,
-                    &pre_window,
-                    &post_window,
-                    slide_size,
-                    buf,
-                    |spectrum| {
-                        let (envelope_order, pitch, formant) = params.lock().unwrap().clone();
-                        voiche::voice_change::process_spectrum(
-                            slide_size,
-                            &fft,
-                            &mut pitch_shift,
-                            envelope_order,
-                            formant,
-                            pitch,
-                            spectrum,
-                        );
-                    },
-                )
-            }
+            let driver = driver.clone();
+            move |input: &[f32], output: &mut [f32]| driver.lock().unwrap().process(input, output)
         };
 
         Processor {
-            transformer: Transformer::new(window_size, slide_size, Box::new(process)),
-            params,
+            transformer: RingTransformer::new(window_size, slide_size, 128, Box::new(process)),
+            driver,
         }
     }
 
@@ -62,10 +46,10 @@ impl Processor {
     }
 
     pub fn set_pitch(&mut self, pitch: f32) {
-        self.params.lock().unwrap().1 = pitch
+        self.driver.lock().unwrap().processor_mut().pitch = pitch
     }
 
     pub fn set_formant(&mut self, formant: f32) {
-        self.params.lock().unwrap().2 = formant
+        self.driver.lock().unwrap().processor_mut().formant = formant
     }
 }
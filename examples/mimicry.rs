@@ -29,18 +29,20 @@ fn main() {
                 pitch,
             );
 
-            transform::transform(window_size, slide_size, process, &buf)
+            transform::transform(window_size, slide_size, process, buf)
         }),
     );
     transform_mic_to_speaker(window_size, slide_size, move |buf| processor.process(buf));
 }
 
+type Process = Box<dyn FnMut(&[f32]) -> Vec<f32>>;
+
 pub struct MimicryProcessor {
     sample_rate: f32,
     mode: Mode,
     buf: Vec<f32>,
     no_voice_time: f32,
-    process: Box<dyn FnMut(&[f32]) -> Vec<f32>>,
+    process: Process,
 }
 
 enum Mode {
@@ -50,7 +52,7 @@ enum Mode {
 }
 
 impl MimicryProcessor {
-    pub fn new(sample_rate: f32, process: Box<dyn FnMut(&[f32]) -> Vec<f32>>) -> Self {
+    pub fn new(sample_rate: f32, process: Process) -> Self {
         Self {
             sample_rate,
             mode: Mode::Wait,
@@ -156,7 +158,7 @@ pub fn transform_mic_to_speaker(
                 .flat_map(|x| x.to_ne_bytes())
                 .collect();
 
-            stdout.write(&buf).unwrap();
+            stdout.write_all(&buf).unwrap();
             stdout.flush().unwrap();
         }
     }
@@ -1,30 +1,31 @@
 mod wav;
 
-use voiche::{api, transform::transform, windows};
+use voiche::{
+    api,
+    auto_tune::{AutoTune, Scale},
+    transform::transform,
+    windows,
+};
 
 fn main() {
     let window_size = 1024;
     let slide_size = window_size / 4;
-    let pitch_fn = |freq: f32| {
-        let nn = (freq / 440.0).log2() * 12.0;
-        // approximately scale
-        let nn_correct = ((nn * (7.0 / 12.0)).round() / (7.0 / 12.0)).round();
-        ((nn_correct - nn) / 12.0).exp2()
-    };
-    // let pitch_fn = |freq: f32| {
-    //     -(freq / 220.0).log2()
-    // };
 
     wav::wav_file_convert("pc", |sample_rate, channels| {
         channels
             .into_iter()
             .map(|buf| {
-                let process = api::pitch_correct(
+                let mut auto_tune = AutoTune::new(sample_rate, slide_size);
+                auto_tune.scale = Scale::Major;
+                auto_tune.retune_speed = 20.0;
+                auto_tune.humanize = 10.0;
+
+                let process = api::auto_tune(
                     windows::hann_window(window_size),
                     windows::trapezoid_window(window_size, window_size - slide_size),
                     slide_size,
                     sample_rate,
-                    pitch_fn,
+                    auto_tune,
                 );
 
                 transform(window_size, slide_size, process, &buf)
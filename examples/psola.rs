@@ -0,0 +1,31 @@
+/// Pitch shift through PSOLA.
+mod wav;
+
+use voiche::{fft::Fft, pitch_detection, sola, windows};
+
+fn main() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let pitch = 1.2;
+    let fft = Fft::new(window_size);
+    let window = windows::hann_window(window_size);
+
+    wav::wav_file_convert("psola", |sample_rate, channels| {
+        let min_wavelength = sample_rate as f32 / (440.0 * 5.0);
+
+        channels
+            .into_iter()
+            .map(|buf| {
+                let marks = pitch_detection::pitch_marks(
+                    &fft,
+                    &window,
+                    &buf,
+                    slide_size,
+                    min_wavelength,
+                    0.6,
+                );
+                sola::psola(&buf, &marks, pitch)
+            })
+            .collect()
+    });
+}
@@ -1,83 +1,51 @@
+/// Convolution reverb with `ir.wav`.
 mod wav;
 
-use std::iter::Sum;
-
 use voiche::{
-    api, apply_window, fft::Fft, num_complex::Complex, num_traits::Zero, transform, windows, Float,
+    convolution::StereoConvolver,
+    resample::{Quality, Resampler},
 };
 
 fn main() {
-    let window_size = 1024 * 4;
-    let slide_size = window_size / 8;
-    let pre_window = windows::hann_window(window_size);
-
-    let scale = 0.2;
-
-    let fft = Fft::new(window_size);
-    let (_, ir_buf) = wav::load("./ir.wav");
-    let ir_specs: Vec<_> = ir_buf[0]
-        .windows(window_size)
-        .step_by(slide_size)
-        .map(|buf| {
-            let mut spec: Vec<_> = apply_window(&pre_window, buf.iter().copied())
-                .map(|x| x * scale)
-                .map(Complex::from)
-                .collect();
-            fft.forward(&mut spec);
-            spec
-        })
-        .collect();
-    dbg!(ir_specs.len());
-
-    wav::wav_file_convert("rv", |_sample_rate, channels| {
-        channels
+    let block_size = 512;
+    let (ir_spec, ir_bufs) = wav::load("./ir.wav");
+
+    wav::wav_file_convert("rv", |sample_rate, channels| {
+        // Match the impulse response to the input, and use the same one for both sides if it's mono.
+        let irs: Vec<Vec<f32>> = ir_bufs
+            .iter()
+            .map(|ir| {
+                let mut resampler =
+                    Resampler::from_rates(ir_spec.sample_rate, sample_rate, Quality::default());
+                let mut output = vec![];
+                resampler.process(ir, &mut output);
+                resampler.finish(&mut output);
+                output.iter().map(|x| x * 0.2).collect()
+            })
+            .collect();
+        let mut convolver = StereoConvolver::new(
+            [&irs[0], irs.last().unwrap()].map(|ir| ir.as_slice()),
+            block_size,
+        );
+        convolver.set_mix(1.0, 0.5);
+
+        // Let the tail ring out.
+        let len = channels[0].len() + irs[0].len() + convolver.latency();
+        let inputs: Vec<Vec<f32>> = [&channels[0], channels.last().unwrap()]
             .into_iter()
             .map(|buf| {
-                let process = reverb(
-                    pre_window.clone(),
-                    windows::trapezoid_window(window_size, window_size - slide_size),
-                    slide_size,
-                    ir_specs.clone(),
-                );
-
-                transform::transform(window_size, slide_size, process, &buf)
+                let mut buf = buf.clone();
+                buf.resize(len, 0.0);
+                buf
             })
-            .collect()
+            .collect();
+        let mut outputs = vec![vec![0.0; len]; 2];
+        let [left, right] = &mut outputs[..] else {
+            unreachable!()
+        };
+        convolver.process([&inputs[0], &inputs[1]], [left, right]);
+
+        outputs.truncate(channels.len());
+        outputs
     });
 }
-
-pub fn reverb<T: Float + Sum + Default>(
-    pre_window: Vec<T>,
-    post_window: Vec<T>,
-    slide_size: usize,
-    ir_specs: Vec<Vec<Complex<T>>>,
-) -> impl FnMut(&[T]) -> Vec<T> {
-    assert_eq!(pre_window.len(), post_window.len());
-
-    let window_size = pre_window.len();
-    let fft = Fft::new(window_size);
-    let mut specs: Vec<Vec<Complex<T>>> = vec![];
-
-    move |buf| {
-        api::retouch_spectrum(
-            &fft,
-            &pre_window,
-            &post_window,
-            slide_size,
-            &buf,
-            |spectrum| {
-                specs.insert(0, spectrum.to_vec());
-                specs.truncate(ir_specs.len());
-
-                spectrum.fill(Complex::zero()); // Comment out to add the dry.
-
-                // Add the wet.
-                for (spec, ir_spec) in specs.iter().zip(ir_specs.iter()) {
-                    for i in 0..window_size {
-                        spectrum[i] = spectrum[i] + spec[i] * ir_spec[i];
-                    }
-                }
-            },
-        )
-    }
-}
@@ -56,7 +56,7 @@ pub fn transform_mic_to_speaker(
                 .flat_map(|x| x.to_ne_bytes())
                 .collect();
 
-            stdout.write(&buf).unwrap();
+            stdout.write_all(&buf).unwrap();
             stdout.flush().unwrap();
         }
     }
@@ -65,7 +65,7 @@ pub fn buffer_cross_fading_write<T: voiche::float::Float>(
         };
         let j = len - overlap_size + i;
         let r = T::from(i).unwrap() / T::from(overlap_size).unwrap();
-        buffer[j] = buffer[j].clone() + (x - buffer[j].clone()) * r;
+        buffer[j] = buffer[j] + (x - buffer[j]) * r;
     }
     buffer.extend(other);
 }
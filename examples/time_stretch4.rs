@@ -0,0 +1,25 @@
+/// Time stretch through the phase vocoder.
+mod wav;
+
+use voiche::{api, windows};
+
+fn main() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let time_rate = 1.5;
+
+    wav::wav_file_convert("ts4", |_sample_rate, channels| {
+        channels
+            .into_iter()
+            .map(|buf| {
+                api::time_stretch(
+                    windows::hann_window(window_size),
+                    windows::hann_window(window_size),
+                    slide_size,
+                    time_rate,
+                    &buf,
+                )
+            })
+            .collect()
+    });
+}
@@ -0,0 +1,19 @@
+/// Time stretch through WSOLA.
+mod wav;
+
+use voiche::sola;
+
+fn main() {
+    let time_rate = 1.5;
+
+    wav::wav_file_convert("ts5", |sample_rate, channels| {
+        let window_size = sample_rate as usize / 25;
+        let slide_size = window_size / 2;
+        let tolerance = window_size / 4;
+
+        channels
+            .into_iter()
+            .map(|buf| sola::wsola(window_size, slide_size, tolerance, time_rate, &buf))
+            .collect()
+    });
+}
@@ -0,0 +1,36 @@
+/// Channel vocoder: the input voice modulates a sawtooth chord.
+mod wav;
+
+use voiche::{api, cross_synthesis::Analysis, transform, windows};
+
+fn main() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+
+    wav::wav_file_convert("vc", |sample_rate, channels| {
+        channels
+            .into_iter()
+            .map(|buf| {
+                // A minor triad on A2.
+                let carrier: Vec<f32> = (0..buf.len())
+                    .map(|i| {
+                        let t = i as f32 / sample_rate as f32;
+                        [110.0f32, 130.81, 164.81]
+                            .iter()
+                            .map(|f| (t * f).fract() * 2.0 - 1.0)
+                            .sum::<f32>()
+                            / 3.0
+                    })
+                    .collect();
+                let process = api::cross_synthesis(
+                    windows::hann_window(window_size),
+                    windows::trapezoid_window(window_size, window_size - slide_size),
+                    slide_size,
+                    Analysis::Bands(24),
+                );
+
+                transform::transform2(window_size, slide_size, process, &buf, &carrier)
+            })
+            .collect()
+    });
+}
@@ -6,10 +6,7 @@ pub fn wav_file_convert(
     filename_suffix: &str,
     process: impl FnMut(u32, Vec<Vec<f32>>) -> Vec<Vec<f32>>,
 ) {
-    let file = std::env::args()
-        .skip(1)
-        .next()
-        .unwrap_or("epic.wav".to_string());
+    let file = std::env::args().nth(1).unwrap_or("epic.wav".to_string());
 
     wav_file_convert_impl(&file, filename_suffix, process);
 }
@@ -19,7 +16,7 @@ pub fn wav_file_convert_impl(
     filename_suffix: &str,
     mut process: impl FnMut(u32, Vec<Vec<f32>>) -> Vec<Vec<f32>>,
 ) {
-    let (mut spec, bufs) = load(&file);
+    let (mut spec, bufs) = load(file);
     dbg!(power(&bufs[0]));
 
     let start = std::time::Instant::now();
@@ -79,8 +76,8 @@ pub fn save(p: impl AsRef<Path>, spec: WavSpec, bufs: Vec<Vec<f32>>) {
             ..
         } => {
             for i in 0..bufs[0].len() {
-                for c in 0..spec.channels as usize {
-                    writer.write_sample(bufs[c][i]).unwrap();
+                for buf in &bufs {
+                    writer.write_sample(buf[i]).unwrap();
                 }
             }
         }
@@ -90,10 +87,10 @@ pub fn save(p: impl AsRef<Path>, spec: WavSpec, bufs: Vec<Vec<f32>>) {
             ..
         } => {
             for i in 0..bufs[0].len() {
-                for c in 0..spec.channels as usize {
+                for buf in &bufs {
                     writer
                         .write_sample(
-                            (bufs[c][i] * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32)
+                            (buf[i] * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32)
                                 as i16,
                         )
                         .unwrap()
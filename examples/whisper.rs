@@ -0,0 +1,52 @@
+/// Whisper by replacing the LPC residual with noise.
+#[path = "../tests/common/mod.rs"]
+mod common;
+mod wav;
+
+use voiche::{lpc, windows};
+
+fn main() {
+    let window_size = 1024;
+    let slide_size = 256;
+    let order = 24;
+    let window = windows::hann_window(window_size);
+
+    wav::wav_file_convert("wh", |_sample_rate, channels| {
+        channels
+            .into_iter()
+            .map(|buf| {
+                // The all-pole filter is too sharp for f32.
+                let buf: Vec<f64> = buf.into_iter().map(|x| x as f64).collect();
+                let noise = common::noise(buf.len(), 1);
+                let mut input_history = vec![0.0; order];
+                let mut output_history = vec![0.0; order];
+                let mut output = Vec::with_capacity(buf.len());
+
+                // The coefficients of the window around each block.
+                for start in (0..buf.len()).step_by(slide_size) {
+                    let mut block = buf[start..(start + slide_size).min(buf.len())].to_vec();
+                    let center = (start + slide_size / 2).saturating_sub(window_size / 2);
+                    let mut frame =
+                        buf[center.min(buf.len())..(center + window_size).min(buf.len())].to_vec();
+                    frame.resize(window_size, 0.0);
+                    let Some(lpc) = lpc::analyze(&frame, &window, order) else {
+                        // Silence
+                        output.resize(output.len() + block.len(), 0.0);
+                        continue;
+                    };
+
+                    lpc::inverse_filter(&lpc.coefficients, &mut input_history, &mut block);
+                    let power = block.iter().map(|x| x * x).sum::<f64>() / block.len() as f64;
+                    // Uniform noise of the same power.
+                    let gain = (power * 12.0).sqrt();
+                    for (x, n) in block.iter_mut().zip(&noise[start..]) {
+                        *x = n * gain;
+                    }
+                    lpc::synthesis_filter(&lpc.coefficients, &mut output_history, &mut block);
+                    output.extend_from_slice(&block);
+                }
+                output.into_iter().map(|x| x as f32).collect()
+            })
+            .collect()
+    });
+}
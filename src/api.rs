@@ -1,14 +1,24 @@
-use std::iter::Sum;
+use std::{cmp::Ordering, iter::Sum};
 
-use crate::num_complex::Complex;
+use crate::{num_complex::Complex, num_traits::Zero};
 
 use crate::{
-    apply_window,
-    fft::{self, Fft},
+    auto_tune::{AutoTune, NoteEvent},
+    cross_synthesis::{Analysis, CrossSynthesis},
+    curve::Curve,
+    envelope::Envelope,
+    fft::Fft,
     float::Float,
-    pitch_detection,
-    pitch_shift::{self, pitch_shifter},
-    voice_change,
+    harmonizer::Harmonizer,
+    pitch_detection::PitchDetector,
+    pitch_shift::{PitchShiftOptions, PitchShiftProcessor},
+    pitch_tracker::{PitchRecord, PitchTracker},
+    spectral::{Linking, MultiSpectralDriver, SpectralDriver, SpectralProcessor},
+    stft,
+    time_stretch::TimeStretcher,
+    transform::buffer_overlapping_write,
+    voice_change::VoiceChange,
+    windows::{self, WindowError},
 };
 
 pub fn pitch_shift<T: Float + Sum>(
@@ -17,24 +27,31 @@ pub fn pitch_shift<T: Float + Sum>(
     slide_size: usize,
     pitch: T,
 ) -> impl FnMut(&[T]) -> Vec<T> {
-    assert_eq!(pre_window.len(), post_window.len());
+    pitch_shift_with_options(
+        pre_window,
+        post_window,
+        slide_size,
+        pitch,
+        PitchShiftOptions::default(),
+    )
+}
 
+pub fn pitch_shift_with_options<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    pitch: T,
+    options: PitchShiftOptions,
+) -> impl FnMut(&[T]) -> Vec<T> {
     let window_size = pre_window.len();
-    let fft = Fft::new(window_size);
-    let mut pitch_shift = pitch_shifter(window_size);
+    let mut driver = SpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        PitchShiftProcessor::with_options(window_size, slide_size, pitch, options),
+    );
 
-    move |buf| {
-        retouch_spectrum(
-            &fft,
-            &pre_window,
-            &post_window,
-            slide_size,
-            &buf,
-            |spectrum| {
-                pitch_shift::process_spectrum(slide_size, &mut pitch_shift, pitch, spectrum);
-            },
-        )
-    }
+    move |buf| driver.process_to_vec(buf)
 }
 
 pub fn voice_change<T: Float + Sum>(
@@ -45,94 +62,460 @@ pub fn voice_change<T: Float + Sum>(
     formant: T,
     pitch: T,
 ) -> impl FnMut(&[T]) -> Vec<T> {
-    assert_eq!(pre_window.len(), post_window.len());
+    voice_change_with_options(
+        pre_window,
+        post_window,
+        slide_size,
+        envelope_order,
+        formant,
+        pitch,
+        PitchShiftOptions::default(),
+    )
+}
 
+pub fn voice_change_with_options<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    envelope_order: usize,
+    formant: T,
+    pitch: T,
+    options: PitchShiftOptions,
+) -> impl FnMut(&[T]) -> Vec<T> {
     let window_size = pre_window.len();
-    let fft = Fft::new(window_size);
-    let mut pitch_shift = pitch_shifter(window_size);
+    let mut driver = SpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        VoiceChange::with_options(
+            window_size,
+            slide_size,
+            envelope_order,
+            formant,
+            pitch,
+            options,
+        ),
+    );
+
+    move |buf| driver.process_to_vec(buf)
+}
+
+/// Same as `pitch_shift()` but the pitch follows a curve.
+///
+/// The curve is evaluated at the center of each window,
+/// in seconds from the start of the first window.
+pub fn pitch_shift_with_curve<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    sample_rate: u32,
+    mut pitch: impl Curve<T>,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    let window_size = pre_window.len();
+    let mut driver = SpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        PitchShiftProcessor::new(window_size, slide_size, T::one()),
+    );
+    let mut times = window_times(window_size, slide_size, sample_rate);
 
     move |buf| {
-        retouch_spectrum(
-            &fft,
-            &pre_window,
-            &post_window,
-            slide_size,
-            &buf,
-            |spectrum| {
-                voice_change::process_spectrum(
-                    slide_size,
-                    &fft,
-                    &mut pitch_shift,
-                    envelope_order,
-                    formant,
-                    pitch,
-                    spectrum,
-                );
-            },
-        )
+        let time = times.next().unwrap();
+        driver.processor_mut().pitch = pitch.value_at(time);
+        driver.process_to_vec(buf)
     }
 }
 
+/// Same as `voice_change()` but the parameters follow curves.
+///
+/// The curves are evaluated at the center of each window,
+/// in seconds from the start of the first window.
+/// The envelope order is rounded and clamped into the valid range.
+pub fn voice_change_with_curves<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    sample_rate: u32,
+    mut envelope_order: impl Curve<T>,
+    mut formant: impl Curve<T>,
+    mut pitch: impl Curve<T>,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    let window_size = pre_window.len();
+    let mut driver = SpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        VoiceChange::new(window_size, slide_size, 1, T::one(), T::one()),
+    );
+    let mut times = window_times(window_size, slide_size, sample_rate);
+
+    move |buf| {
+        let time = times.next().unwrap();
+        let voice_change = driver.processor_mut();
+        voice_change.envelope = Envelope::Cepstrum(
+            envelope_order
+                .value_at(time)
+                .round()
+                .to_usize()
+                .unwrap_or(1)
+                .clamp(1, window_size / 2 - 1),
+        );
+        voice_change.formant = formant.value_at(time);
+        voice_change.pitch = pitch.value_at(time);
+        driver.process_to_vec(buf)
+    }
+}
+
+fn window_times<T: Float>(
+    window_size: usize,
+    slide_size: usize,
+    sample_rate: u32,
+) -> impl Iterator<Item = T> {
+    let sample_rate = sample_rate as f64;
+    (0..).map(move |i: usize| {
+        T::from((i * slide_size + window_size / 2) as f64 / sample_rate).unwrap()
+    })
+}
+
+/// Run `processor` on windows of several channels, for `transform::transform_multi()`.
+/// With `Linking::Linked`, one processor is shared by all the channels.
+pub fn spectral_multi<T: Float, P: SpectralProcessor<T> + Clone>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    channels: usize,
+    linking: Linking,
+    processor: P,
+) -> impl FnMut(&[Vec<T>]) -> Vec<Vec<T>> {
+    let mut driver = MultiSpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        channels,
+        linking,
+        processor,
+    );
+    move |bufs| driver.process_to_vec(bufs)
+}
+
+/// Multichannel version of `pitch_shift()`.
+pub fn pitch_shift_multi<T: Float>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    channels: usize,
+    linking: Linking,
+    pitch: T,
+) -> impl FnMut(&[Vec<T>]) -> Vec<Vec<T>> {
+    let window_size = pre_window.len();
+    spectral_multi(
+        pre_window,
+        post_window,
+        slide_size,
+        channels,
+        linking,
+        PitchShiftProcessor::new(window_size, slide_size, pitch),
+    )
+}
+
+/// Multichannel version of `voice_change()`.
+/// With `Linking::Linked`, the formant envelope is estimated once from all the channels.
+#[allow(clippy::too_many_arguments)]
+pub fn voice_change_multi<T: Float>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    channels: usize,
+    linking: Linking,
+    envelope_order: usize,
+    formant: T,
+    pitch: T,
+) -> impl FnMut(&[Vec<T>]) -> Vec<Vec<T>> {
+    let window_size = pre_window.len();
+    spectral_multi(
+        pre_window,
+        post_window,
+        slide_size,
+        channels,
+        linking,
+        VoiceChange::new(window_size, slide_size, envelope_order, formant, pitch),
+    )
+}
+
+/// Apply the spectral envelope of a modulator to a carrier, window by window,
+/// for `transform::transform2()` and `transform::Transformer2`.
+/// The first input is the modulator and the second is the carrier.
+pub fn cross_synthesis<T: Float>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    analysis: Analysis,
+) -> impl FnMut(&[T], &[T]) -> Vec<T> {
+    let mut cross_synthesis = CrossSynthesis::new(pre_window, post_window, slide_size, analysis);
+    move |modulator, carrier| cross_synthesis.process_to_vec(modulator, carrier)
+}
+
+/// Change the duration of `buf` by `time_rate` without changing the pitch.
+///
+/// The output has `buf.len() * time_rate` samples.
+/// For streaming, use `time_stretch::TimeStretcher`.
+pub fn time_stretch<T: Float>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    time_rate: T,
+    buf: &[T],
+) -> Vec<T> {
+    let mut time_stretcher = TimeStretcher::new(pre_window, post_window, slide_size, time_rate);
+    let latency = time_stretcher.window_size() - time_stretcher.slide_size();
+    time_stretcher.input_slice(buf);
+    let mut output = Vec::new();
+    time_stretcher.finish(&mut output);
+
+    let start = (T::from(latency).unwrap() * time_rate)
+        .round()
+        .to_usize()
+        .unwrap();
+    let len = (T::from(buf.len()).unwrap() * time_rate)
+        .round()
+        .to_usize()
+        .unwrap();
+    output.drain(..start.min(output.len()));
+    output.resize(len, T::zero());
+    output
+}
+
 pub fn pitch_correct<T: Float + Sum, F: FnMut(T) -> T>(
     pre_window: Vec<T>,
     post_window: Vec<T>,
     slide_size: usize,
     sample_rate: u32,
+    pitch_fn: F,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    let tracker = PitchTracker::with_mpm(pre_window.len(), sample_rate);
+    pitch_correct_with_tracker(pre_window, post_window, slide_size, tracker, pitch_fn)
+}
+
+/// Same as `pitch_correct()` but the pitch is detected by `tracker`.
+/// `pitch_fn` is called with the frequency of voiced hops.
+pub fn pitch_correct_with_tracker<T: Float + Sum, D: PitchDetector<T>, F: FnMut(T) -> T>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    tracker: PitchTracker<T, D>,
     mut pitch_fn: F,
 ) -> impl FnMut(&[T]) -> Vec<T> {
-    let sample_rate = T::from(sample_rate).unwrap();
+    pitch_correct_by_record(pre_window, post_window, slide_size, tracker, move |record| {
+        if record.voiced {
+            pitch_fn(record.frequency)
+        } else {
+            T::one()
+        }
+    })
+}
+
+/// Multichannel version of `pitch_correct()`.
+/// The pitch is detected once from the average of the channels, so every channel is shifted alike.
+pub fn pitch_correct_multi<T: Float, F: FnMut(T) -> T>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    sample_rate: u32,
+    channels: usize,
+    linking: Linking,
+    mut pitch_fn: F,
+) -> impl FnMut(&[Vec<T>]) -> Vec<Vec<T>> {
     let window_size = pre_window.len();
-    let fft = Fft::new(window_size);
-    let mut pitch_shift = pitch_shift::pitch_shifter(window_size);
-    let min_wavelength = sample_rate / T::from(440.0 * 5.0).unwrap();
-    let peak_threshold = T::from(0.4).unwrap();
+    let mut tracker = PitchTracker::with_mpm(window_size, sample_rate);
+    let mut driver = MultiSpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        channels,
+        linking,
+        PitchShiftProcessor::new(window_size, slide_size, T::one()),
+    );
+    let mut mix = vec![T::zero(); window_size];
+    let scale = T::one() / T::from(channels).unwrap();
+
+    move |bufs: &[Vec<T>]| {
+        mix.fill(T::zero());
+        for buf in bufs {
+            for (m, &x) in mix.iter_mut().zip(buf) {
+                *m = *m + x * scale;
+            }
+        }
+        let record = tracker.process(&mix);
+        let pitch = if record.voiced {
+            pitch_fn(record.frequency)
+        } else {
+            T::one()
+        };
+        for processor in driver.processors_mut() {
+            processor.pitch = pitch;
+        }
+        driver.process_to_vec(bufs)
+    }
+}
+
+/// Correct the pitch to the notes of the scale of `auto_tune`.
+/// `auto_tune` must be created with the same `slide_size`.
+pub fn auto_tune<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    sample_rate: u32,
+    mut auto_tune: AutoTune<T>,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    let tracker = PitchTracker::with_mpm(pre_window.len(), sample_rate);
+    pitch_correct_by_record(pre_window, post_window, slide_size, tracker, move |record| {
+        auto_tune.process(record)
+    })
+}
+
+/// Same as `auto_tune()` but the pitch is pulled to the notes held by `notes` at each window,
+/// falling back to the scale while no note is held.
+///
+/// The times of `notes` are in seconds from the start of the signal.
+pub fn auto_tune_with_notes<T: Float + Sum>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    sample_rate: u32,
+    mut auto_tune: AutoTune<T>,
+    mut notes: Vec<NoteEvent<T>>,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    notes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+    let mut notes = notes.into_iter().peekable();
+    let mut times = window_times(pre_window.len(), slide_size, sample_rate);
+    let tracker = PitchTracker::with_mpm(pre_window.len(), sample_rate);
+    pitch_correct_by_record(pre_window, post_window, slide_size, tracker, move |record| {
+        let time = times.next().unwrap();
+        while let Some(event) = notes.next_if(|event| event.time <= time) {
+            if event.on {
+                auto_tune.note_on(event.note);
+            } else {
+                auto_tune.note_off(event.note);
+            }
+        }
+        auto_tune.process(record)
+    })
+}
+
+fn pitch_correct_by_record<T: Float + Sum, D: PitchDetector<T>>(
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    mut tracker: PitchTracker<T, D>,
+    mut pitch_fn: impl FnMut(&PitchRecord<T>) -> T,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    let window_size = pre_window.len();
+    let mut driver = SpectralDriver::new(
+        pre_window,
+        post_window,
+        slide_size,
+        PitchShiftProcessor::new(window_size, slide_size, T::one()),
+    );
 
     move |buf: &[T]| {
-        let b = buf.to_vec();
+        let record = tracker.process(buf);
+        driver.processor_mut().pitch = pitch_fn(&record);
+        driver.process_to_vec(buf)
+    }
+}
 
-        retouch_spectrum(
-            &fft,
-            &pre_window,
-            &post_window,
-            slide_size,
-            buf,
-            |spectrum| {
-                let nsdf = pitch_detection::compute_nsdf(&fft, &b);
-                let peaks = pitch_detection::compute_peaks(&nsdf[..nsdf.len() / 2]);
-                let peaks: Vec<_> = peaks.into_iter().filter(|p| min_wavelength < p.0).collect();
-                let max_peak = peaks.iter().fold(T::zero(), |a, p| a.max(p.1));
-                let pitch = if peak_threshold < max_peak {
-                    let t = T::from(0.9).unwrap();
-                    let peak = peaks.iter().find(|p| max_peak * t <= p.1).unwrap().clone();
-                    let wavelength = peak.0;
-                    let freq = sample_rate / wavelength;
-                    pitch_fn(freq)
-                } else {
-                    T::one()
-                };
-                pitch_shift::process_spectrum(slide_size, &mut pitch_shift, pitch, spectrum);
-            },
-        )
+/// Mix the voices of `harmonizer` into a mono signal.
+pub fn harmonizer<T: Float, D: PitchDetector<T>>(
+    mut harmonizer: Harmonizer<T, D>,
+) -> impl FnMut(&[T]) -> Vec<T> {
+    move |buf: &[T]| {
+        let mut output = vec![T::zero(); buf.len()];
+        harmonizer.process(buf, &mut [&mut output]);
+        output
+    }
+}
+
+/// Mix the voices of `harmonizer` into the left and right channels.
+/// The windows are taken like `transform::transform()`, so are the lengths of the outputs.
+pub fn harmonizer_stereo<T: Float, D: PitchDetector<T>>(
+    mut harmonizer: Harmonizer<T, D>,
+    buf: &[T],
+) -> [Vec<T>; 2] {
+    let window_size = harmonizer.window_size();
+    let slide_size = harmonizer.slide_size();
+    let mut outputs = [Vec::with_capacity(buf.len()), Vec::with_capacity(buf.len())];
+    if buf.is_empty() {
+        return outputs;
+    }
+
+    let mut input = vec![T::zero(); window_size];
+    let mut left = vec![T::zero(); window_size];
+    let mut right = vec![T::zero(); window_size];
+    for i in (0..buf.len()).step_by(slide_size) {
+        let window = &buf[i..(i + window_size).min(buf.len())];
+        input[..window.len()].copy_from_slice(window);
+        input[window.len()..].fill(T::zero());
+        harmonizer.process(&input, &mut [&mut left, &mut right]);
+        buffer_overlapping_write(slide_size, &mut outputs[0], &left);
+        buffer_overlapping_write(slide_size, &mut outputs[1], &right);
     }
+    outputs
 }
 
+/// Process the spectrum of a window and return it windowed for overlap-add,
+/// normalized by `windows::synthesis_window()` for perfect reconstruction.
+///
+/// Panics if the windows cannot reconstruct the signal. See `try_retouch_spectrum()`.
 pub fn retouch_spectrum<T: Float + Sum>(
     fft: &Fft<T>,
     pre_window: &[T],
     post_window: &[T],
     slide_size: usize,
     buf: &[T],
+    process: impl FnMut(&mut [Complex<T>]),
+) -> Vec<T> {
+    match try_retouch_spectrum(fft, pre_window, post_window, slide_size, buf, process) {
+        Ok(output) => output,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Same as `retouch_spectrum()` but returns the error if the windows cannot reconstruct the signal.
+pub fn try_retouch_spectrum<T: Float + Sum>(
+    fft: &Fft<T>,
+    pre_window: &[T],
+    post_window: &[T],
+    slide_size: usize,
+    buf: &[T],
+    process: impl FnMut(&mut [Complex<T>]),
+) -> Result<Vec<T>, WindowError> {
+    let post_window =
+        windows::check_reconstruction(pre_window, post_window, slide_size)?.normalize(post_window);
+    Ok(retouch_spectrum_normalized(
+        fft,
+        pre_window,
+        &post_window,
+        buf,
+        process,
+    ))
+}
+
+/// Same as `retouch_spectrum()` but `synthesis_window` is applied as it is,
+/// which saves validating and normalizing the windows on every call.
+/// Make it once with `windows::synthesis_window()`.
+pub fn retouch_spectrum_normalized<T: Float + Sum>(
+    fft: &Fft<T>,
+    pre_window: &[T],
+    synthesis_window: &[T],
+    buf: &[T],
     mut process: impl FnMut(&mut [Complex<T>]),
 ) -> Vec<T> {
-    let mut spec: Vec<_> = apply_window(pre_window, buf.iter().copied())
-        .map(Complex::from)
-        .collect();
-    fft.forward(&mut spec);
-    process(&mut spec);
-    fft.inverse(&mut spec);
-    fft::fix_scale(&mut spec);
-    let output_scale = T::from(buf.len() * slide_size).unwrap()
-        / pre_window.iter().copied().sum::<T>()
-        / post_window.iter().copied().sum::<T>();
-    apply_window(post_window, spec.iter().map(|x| x.re * output_scale)).collect()
+    let mut buffer = vec![T::zero(); buf.len()];
+    let mut half_spectrum = vec![Complex::zero(); fft.half_len()];
+    let mut spectrum = vec![Complex::zero(); buf.len()];
+    stft::analyze(fft, pre_window, buf, &mut buffer, &mut half_spectrum);
+    process.process_half(&mut half_spectrum, &mut spectrum);
+    stft::synthesize(fft, synthesis_window, &mut half_spectrum, &mut buffer);
+    buffer
 }
@@ -0,0 +1,246 @@
+//! Pitch correction to the notes of a scale.
+
+use crate::{pitch_tracker::PitchRecord, Float};
+
+/// Notes of a scale, relative to the root key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scale {
+    #[default]
+    Chromatic,
+    Major,
+    /// Natural minor.
+    Minor,
+    /// `mask[i]` tells whether the note `i` semitones above the root is in the scale.
+    Custom([bool; 12]),
+}
+
+impl Scale {
+    pub fn mask(&self) -> [bool; 12] {
+        let from_intervals = |intervals: &[usize]| {
+            let mut mask = [false; 12];
+            for &i in intervals {
+                mask[i] = true;
+            }
+            mask
+        };
+        match self {
+            Scale::Chromatic => [true; 12],
+            Scale::Major => from_intervals(&[0, 2, 4, 5, 7, 9, 11]),
+            Scale::Minor => from_intervals(&[0, 2, 3, 5, 7, 8, 10]),
+            Scale::Custom(mask) => *mask,
+        }
+    }
+
+    /// MIDI note number of the note in the scale of `key` nearest to `note`,
+    /// if the scale has any note.
+    pub fn nearest<T: Float>(&self, key: usize, note: T) -> Option<T> {
+        let mask = self.mask();
+        let nearest = note.round().to_i64().unwrap();
+        // Search outward so that the nearest note is found first.
+        (0..=6)
+            .flat_map(|d| [nearest - d, nearest + d])
+            .filter(|&n| mask[(n - key as i64).rem_euclid(12) as usize])
+            .map(|n| T::from(n).unwrap())
+            .min_by(|&a, &b| {
+                (a - note)
+                    .abs()
+                    .partial_cmp(&(b - note).abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// The note `degrees` steps of the scale of `key` above `note`, or below if negative.
+    /// `note` itself need not be in the scale.
+    pub fn step(&self, key: usize, note: i64, degrees: i32) -> i64 {
+        let mask = self.mask();
+        if !mask.contains(&true) {
+            return note;
+        }
+        let in_scale = |n: i64| mask[(n - key as i64).rem_euclid(12) as usize];
+        let direction = degrees.signum() as i64;
+        let mut note = note;
+        for _ in 0..degrees.unsigned_abs() {
+            note += direction;
+            while !in_scale(note) {
+                note += direction;
+            }
+        }
+        note
+    }
+}
+
+/// A MIDI note starting (`on`) or ending at `time` in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteEvent<T> {
+    pub time: T,
+    pub note: u8,
+    pub on: bool,
+}
+
+/// Decides the pitch shift of each hop to pull the detected pitch to the nearest note of a scale.
+///
+/// While notes are held by `note_on()`, the pitch is pulled to the latest held note instead.
+///
+/// Feed it the records of a `pitch_tracker::PitchTracker`, or use `api::auto_tune()`.
+/// It does not allocate, so it can run on an audio thread.
+#[derive(Clone, Debug)]
+pub struct AutoTune<T: Float> {
+    /// Root note, 0 for C to 11 for B.
+    pub key: usize,
+    pub scale: Scale,
+    /// Frequency of A4 in Hz.
+    pub reference: T,
+    /// Time in milliseconds for the correction to catch up with a new note.
+    /// 0 snaps to the notes instantly.
+    pub retune_speed: T,
+    /// Deviations from the note within this many cents are left uncorrected,
+    /// which keeps vibrato and small inflections natural.
+    pub humanize: T,
+    hop_ms: T,
+    correction: Option<T>,
+    /// Held MIDI notes in the order they were pressed.
+    held: Vec<u8>,
+}
+
+impl<T: Float> AutoTune<T> {
+    /// `slide_size` is the hop in samples between the calls to `process()`.
+    pub fn new(sample_rate: u32, slide_size: usize) -> Self {
+        AutoTune {
+            key: 0,
+            scale: Scale::Chromatic,
+            reference: T::from(440.0).unwrap(),
+            retune_speed: T::zero(),
+            humanize: T::zero(),
+            hop_ms: T::from(slide_size as f64 * 1000.0 / sample_rate as f64).unwrap(),
+            correction: None,
+            held: Vec::with_capacity(128),
+        }
+    }
+
+    /// Forget the correction and release all the notes.
+    pub fn reset(&mut self) {
+        self.correction = None;
+        self.held.clear();
+    }
+
+    pub fn note_on(&mut self, note: u8) {
+        self.note_off(note);
+        self.held.push(note);
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        self.held.retain(|&n| n != note);
+    }
+
+    pub fn held_note(&self) -> Option<u8> {
+        self.held.last().copied()
+    }
+
+    /// MIDI note number of the note in the scale nearest to `note`, if the scale has any note.
+    pub fn target_note(&self, note: T) -> Option<T> {
+        self.scale.nearest(self.key, note)
+    }
+
+    /// MIDI note number of `frequency`, fractional, tuned to `reference`.
+    pub fn note(&self, frequency: T) -> T {
+        (frequency / self.reference).log2() * T::from(12).unwrap() + T::from(69).unwrap()
+    }
+
+    /// Returns the pitch ratio for the hop of `record`. Unvoiced hops are not shifted.
+    pub fn process(&mut self, record: &PitchRecord<T>) -> T {
+        let target = record.voiced.then(|| self.note(record.frequency));
+        let target = target.and_then(|note| {
+            let target = match self.held_note() {
+                Some(held) => T::from(held).unwrap(),
+                None => self.target_note(note)?,
+            };
+            let deviation = target - note;
+            let humanize = self.humanize / T::from(100).unwrap();
+            Some(if deviation.abs() <= humanize {
+                T::zero()
+            } else {
+                deviation - deviation.signum() * humanize
+            })
+        });
+        let Some(target) = target else {
+            self.correction = None;
+            return T::one();
+        };
+
+        // One-pole smoothing of the correction in semitones. A voiced segment starts on target.
+        let correction = match self.correction {
+            Some(correction) if T::zero() < self.retune_speed => {
+                let a = (-self.hop_ms / self.retune_speed).exp();
+                correction * a + target * (T::one() - a)
+            }
+            _ => target,
+        };
+        self.correction = Some(correction);
+        (correction / T::from(12).unwrap()).exp2()
+    }
+}
+
+#[test]
+fn test() {
+    let record = |frequency: f64| PitchRecord {
+        frequency,
+        confidence: 1.0,
+        voiced: true,
+    };
+    let semitones = |ratio: f64| ratio.log2() * 12.0;
+
+    let mut auto_tune = AutoTune::new(16000, 160);
+    // A4 + 40 cents goes down to A4.
+    assert!((semitones(auto_tune.process(&record(450.4))) + 0.4).abs() < 0.01);
+
+    // G#4 + 30 cents is not in C major, so it goes to A4.
+    auto_tune.scale = Scale::Major;
+    let g_sharp = 440.0 * (-0.7f64 / 12.0).exp2();
+    assert!((semitones(auto_tune.process(&record(g_sharp))) - 0.7).abs() < 1e-6);
+    // ... but G# is in E major.
+    auto_tune.key = 4;
+    assert!((semitones(auto_tune.process(&record(g_sharp))) + 0.3).abs() < 1e-6);
+    let mut mask = [false; 12];
+    mask[0] = true;
+    auto_tune.scale = Scale::Custom(mask);
+    assert_eq!(auto_tune.target_note(69.0), Some(64.0));
+    // A third and a fifth below C4 in C major.
+    assert_eq!(Scale::Major.step(0, 60, -2), 57);
+    assert_eq!(Scale::Major.step(0, 60, -4), 53);
+    assert_eq!(Scale::Major.step(0, 64, 1), 65);
+
+    // Slow retuning lets a pitch change pass at first.
+    auto_tune.scale = Scale::Chromatic;
+    auto_tune.key = 0;
+    auto_tune.retune_speed = 100.0;
+    auto_tune.reset();
+    assert_eq!(auto_tune.process(&record(440.0)), 1.0);
+    let sharp = 440.0 * (0.3f64 / 12.0).exp2();
+    assert!(semitones(auto_tune.process(&record(sharp))) > -0.05);
+    for _ in 0..100 {
+        auto_tune.process(&record(sharp));
+    }
+    assert!((semitones(auto_tune.process(&record(sharp))) + 0.3).abs() < 0.01);
+
+    // The reference tuning moves the notes.
+    auto_tune.retune_speed = 0.0;
+    auto_tune.reference = 432.0;
+    let deviation = (440.0f64 / 432.0).log2() * 12.0;
+    assert!((semitones(auto_tune.process(&record(440.0))) + deviation).abs() < 1e-6);
+    auto_tune.reference = 440.0;
+
+    // A held note overrides the scale.
+    auto_tune.note_on(72);
+    auto_tune.note_on(71);
+    assert!((semitones(auto_tune.process(&record(440.0))) - 2.0).abs() < 1e-6);
+    auto_tune.note_off(71);
+    assert!((semitones(auto_tune.process(&record(440.0))) - 3.0).abs() < 1e-6);
+    auto_tune.note_off(72);
+    assert_eq!(auto_tune.process(&record(440.0)), 1.0);
+
+    // Humanize keeps small deviations.
+    auto_tune.humanize = 20.0;
+    let vibrato = 440.0 * (0.15f64 / 12.0).exp2();
+    assert_eq!(auto_tune.process(&record(vibrato)), 1.0);
+    assert!((semitones(auto_tune.process(&record(450.4))) + 0.2).abs() < 0.01);
+}
@@ -0,0 +1,241 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use hound::SampleFormat;
+use voiche::{
+    pitch_detection::{Mpm, PitchDetector, Pyin, Yin},
+    pitch_tracker::{pitch_contour, PitchRecord},
+    windows,
+};
+
+/// Detect the pitch of a WAV file frame by frame
+/// and write time, f0, confidence and MIDI note as CSV or JSON.
+#[derive(Parser)]
+#[command(name = "voiche-pitch")]
+struct Args {
+    /// Input WAV file. Channels are mixed down to mono.
+    input: PathBuf,
+    /// Output file. Writes to stdout if omitted.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Output format. Guessed from the output extension if omitted, otherwise CSV.
+    #[arg(short, long)]
+    format: Option<Format>,
+    #[arg(short, long, value_enum, default_value_t = Detector::Pyin)]
+    detector: Detector,
+    /// Window size in samples.
+    #[arg(short, long, default_value_t = 2048)]
+    window: usize,
+    /// Hop size in samples.
+    #[arg(long, default_value_t = 256)]
+    hop: usize,
+    /// Lowest frequency to detect in Hz.
+    #[arg(long, default_value_t = 60.0)]
+    min_freq: f64,
+    /// Highest frequency to detect in Hz.
+    #[arg(long, default_value_t = 1000.0)]
+    max_freq: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Detector {
+    Mpm,
+    Yin,
+    Pyin,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.window == 0 || args.hop == 0 {
+        exit("window and hop must be positive");
+    }
+    if !(0.0 < args.min_freq && args.min_freq < args.max_freq) {
+        exit("min-freq must be positive and below max-freq");
+    }
+
+    let (sample_rate, buf) = load(&args.input).unwrap_or_else(|e| exit(&e.to_string()));
+    let min_wavelength = sample_rate as f64 / args.max_freq;
+    let max_wavelength = sample_rate as f64 / args.min_freq;
+    let mut detector: Box<dyn PitchDetector<f64>> = match args.detector {
+        Detector::Mpm => Box::new(Mpm::new(
+            windows::rectangular_window(args.window),
+            min_wavelength,
+            0.4,
+        )),
+        Detector::Yin => Box::new(Yin::new(args.window, min_wavelength, max_wavelength)),
+        Detector::Pyin => Box::new(Pyin::new(args.window, min_wavelength, max_wavelength)),
+    };
+
+    let mut records = pitch_contour(&mut detector, sample_rate, args.window, args.hop, &buf);
+    // MPM has no upper limit of the wavelength.
+    for record in &mut records {
+        if record.voiced && record.frequency < args.min_freq {
+            *record = PitchRecord {
+                frequency: 0.0,
+                confidence: 0.0,
+                voiced: false,
+            };
+        }
+    }
+    // Times are of the window centers.
+    let times = (0..records.len())
+        .map(|i| (i * args.hop + args.window / 2) as f64 / sample_rate as f64);
+    let rows = times.zip(records);
+
+    let format = args
+        .format
+        .unwrap_or_else(|| guess_format(args.output.as_deref()));
+    let result = match &args.output {
+        Some(path) => File::create(path)
+            .and_then(|file| write(&mut BufWriter::new(file), format, rows)),
+        None => write(&mut io::stdout().lock(), format, rows),
+    };
+    if let Err(e) = result {
+        exit(&e.to_string());
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("voiche-pitch: {}", message);
+    std::process::exit(1)
+}
+
+fn load(path: &PathBuf) -> Result<(u32, Vec<f64>), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f64> = match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|x| x.map(|x| x as f64))
+            .collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+            reader
+                .samples::<i32>()
+                .map(|x| x.map(|x| x as f64 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = spec.channels as usize;
+    let buf = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f64>() / channels as f64)
+        .collect();
+    Ok((spec.sample_rate, buf))
+}
+
+/// Guesses the format from the extension of the output, CSV by default.
+fn guess_format(output: Option<&Path>) -> Format {
+    match output.and_then(|p| p.extension()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+        _ => Format::Csv,
+    }
+}
+
+fn write(
+    out: &mut impl Write,
+    format: Format,
+    rows: impl Iterator<Item = (f64, PitchRecord<f64>)>,
+) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            writeln!(out, "time,f0,confidence,midi")?;
+            for (time, record) in rows {
+                let midi = record
+                    .midi_note()
+                    .map_or(String::new(), |n| format!("{:.3}", n));
+                writeln!(
+                    out,
+                    "{:.6},{:.3},{:.4},{}",
+                    time, record.frequency, record.confidence, midi
+                )?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, (time, record)) in rows.enumerate() {
+                let midi = record
+                    .midi_note()
+                    .map_or("null".to_string(), |n| format!("{:.3}", n));
+                if 0 < i {
+                    writeln!(out, ",")?;
+                }
+                write!(
+                    out,
+                    "  {{\"time\": {:.6}, \"f0\": {:.3}, \"confidence\": {:.4}, \"midi\": {}}}",
+                    time, record.frequency, record.confidence, midi
+                )?;
+            }
+            writeln!(out, "\n]")?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+fn rows() -> Vec<(f64, PitchRecord<f64>)> {
+    vec![
+        (
+            0.064,
+            PitchRecord {
+                frequency: 440.0,
+                confidence: 0.95,
+                voiced: true,
+            },
+        ),
+        (
+            0.08,
+            PitchRecord {
+                frequency: 0.0,
+                confidence: 0.0,
+                voiced: false,
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+fn format_rows(format: Format) -> String {
+    let mut out = Vec::new();
+    write(&mut out, format, rows().into_iter()).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_csv() {
+    assert_eq!(
+        format_rows(Format::Csv),
+        "time,f0,confidence,midi\n\
+         0.064000,440.000,0.9500,69.000\n\
+         0.080000,0.000,0.0000,\n"
+    );
+}
+
+#[test]
+fn test_json() {
+    assert_eq!(
+        format_rows(Format::Json),
+        "[\n  \
+         {\"time\": 0.064000, \"f0\": 440.000, \"confidence\": 0.9500, \"midi\": 69.000},\n  \
+         {\"time\": 0.080000, \"f0\": 0.000, \"confidence\": 0.0000, \"midi\": null}\n]\n"
+    );
+}
+
+#[test]
+fn test_guess_format() {
+    assert_eq!(guess_format(None), Format::Csv);
+    assert_eq!(guess_format(Some(Path::new("out.csv"))), Format::Csv);
+    assert_eq!(guess_format(Some(Path::new("out.JSON"))), Format::Json);
+    assert_eq!(guess_format(Some(Path::new("out"))), Format::Csv);
+}
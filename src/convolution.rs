@@ -0,0 +1,277 @@
+//! Convolution with long impulse responses, e.g. for reverb.
+
+use crate::{fft::Fft, num_complex::Complex, num_traits::Zero, Float};
+
+/// Streaming convolution by uniformly partitioned overlap-save.
+///
+/// The impulse response is split into partitions of `block_size`,
+/// and the spectra of the past input blocks are kept to be multiplied with them,
+/// so the cost per sample grows with `ir.len() / block_size`, not with `ir.len()`.
+/// The latency is `block_size`, which also delays the dry signal to stay aligned.
+/// It does not allocate after construction, except in `set_ir()`.
+///
+/// # Example
+/// ```
+/// # use voiche::convolution::Convolver;
+/// let ir = vec![0.5f32; 4800];
+/// let mut convolver = Convolver::new(&ir, 256);
+/// convolver.dry = 1.0;
+/// convolver.wet = 0.3;
+///
+/// let input = vec![0.0; 100];
+/// let mut output = vec![0.0; 100];
+/// convolver.process(&input, &mut output);
+/// ```
+#[derive(Clone)]
+pub struct Convolver<T: Float> {
+    /// Gain of the convolved signal. 1 by default.
+    pub wet: T,
+    /// Gain of the input. 0 by default.
+    pub dry: T,
+    block_size: usize,
+    fft: Fft<T>,
+    /// Half spectra of the partitions of the impulse response.
+    ir_spectra: Vec<Vec<Complex<T>>>,
+    /// Half spectra of the latest input blocks, as a ring buffer from `position`.
+    input_spectra: Vec<Vec<Complex<T>>>,
+    position: usize,
+    /// The previous and the current input block.
+    input: Vec<T>,
+    output: Vec<T>,
+    index: usize,
+    buffer: Vec<T>,
+    accumulator: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: Float> Convolver<T> {
+    pub fn new(ir: &[T], block_size: usize) -> Self {
+        assert!(0 < block_size);
+
+        let fft = Fft::new(block_size * 2);
+        let mut convolver = Convolver {
+            wet: T::one(),
+            dry: T::zero(),
+            block_size,
+            ir_spectra: Vec::new(),
+            input_spectra: Vec::new(),
+            position: 0,
+            input: vec![T::zero(); block_size * 2],
+            output: vec![T::zero(); block_size],
+            index: 0,
+            buffer: vec![T::zero(); block_size * 2],
+            accumulator: vec![Complex::zero(); fft.half_len()],
+            scratch: vec![Complex::zero(); fft.scratch_len()],
+            fft,
+        };
+        convolver.set_ir(ir);
+        convolver
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn latency(&self) -> usize {
+        self.block_size
+    }
+
+    /// Replace the impulse response.
+    /// The input so far is kept if the number of partitions does not change.
+    pub fn set_ir(&mut self, ir: &[T]) {
+        let block_size = self.block_size;
+        let partitions = ir.len().div_ceil(block_size).max(1);
+        // The FFT is unnormalized, so the scale is put on the impulse response.
+        let scale = T::one() / T::from(block_size * 2).unwrap();
+        self.ir_spectra = (0..partitions)
+            .map(|p| {
+                let part =
+                    &ir[(p * block_size).min(ir.len())..((p + 1) * block_size).min(ir.len())];
+                self.buffer.fill(T::zero());
+                for (b, &x) in self.buffer.iter_mut().zip(part) {
+                    *b = x * scale;
+                }
+                let mut spectrum = vec![Complex::zero(); self.fft.half_len()];
+                self.fft.forward_real_with_scratch(
+                    &mut self.buffer,
+                    &mut spectrum,
+                    &mut self.scratch,
+                );
+                spectrum
+            })
+            .collect();
+        self.input_spectra
+            .resize(partitions, vec![Complex::zero(); self.fft.half_len()]);
+        self.position %= partitions;
+    }
+
+    pub fn reset(&mut self) {
+        for spectrum in &mut self.input_spectra {
+            spectrum.fill(Complex::zero());
+        }
+        self.input.fill(T::zero());
+        self.output.fill(T::zero());
+        self.index = 0;
+    }
+
+    /// Convolve `input` into `output` of the same length, which can be of any size.
+    pub fn process(&mut self, input: &[T], output: &mut [T]) {
+        assert_eq!(input.len(), output.len());
+        let block_size = self.block_size;
+
+        let mut i = 0;
+        while i < input.len() {
+            let len = (block_size - self.index).min(input.len() - i);
+            for j in 0..len {
+                let k = self.index + j;
+                // The dry signal is of the previous block to be delayed as much as the wet.
+                output[i + j] = self.output[k] * self.wet + self.input[k] * self.dry;
+                self.input[block_size + k] = input[i + j];
+            }
+            self.index += len;
+            i += len;
+
+            if self.index == block_size {
+                self.process_block();
+                self.index = 0;
+            }
+        }
+    }
+
+    fn process_block(&mut self) {
+        let block_size = self.block_size;
+        let partitions = self.ir_spectra.len();
+
+        // Spectrum of the previous and the current block, newest at `position`.
+        self.position = (self.position + partitions - 1) % partitions;
+        self.buffer.copy_from_slice(&self.input);
+        self.fft.forward_real_with_scratch(
+            &mut self.buffer,
+            &mut self.input_spectra[self.position],
+            &mut self.scratch,
+        );
+
+        self.accumulator.fill(Complex::zero());
+        for (p, ir_spectrum) in self.ir_spectra.iter().enumerate() {
+            let input_spectrum = &self.input_spectra[(self.position + p) % partitions];
+            for ((a, &x), &h) in self
+                .accumulator
+                .iter_mut()
+                .zip(input_spectrum)
+                .zip(ir_spectrum)
+            {
+                *a = *a + x * h;
+            }
+        }
+        self.fft.inverse_real_with_scratch(
+            &mut self.accumulator,
+            &mut self.buffer,
+            &mut self.scratch,
+        );
+
+        // The first half is aliased and is discarded.
+        self.output.copy_from_slice(&self.buffer[block_size..]);
+        self.input.copy_within(block_size.., 0);
+    }
+}
+
+/// A pair of `Convolver`s for an impulse response of two channels.
+///
+/// For a mono source, give the same input to both channels.
+#[derive(Clone)]
+pub struct StereoConvolver<T: Float> {
+    pub convolvers: [Convolver<T>; 2],
+}
+
+impl<T: Float> StereoConvolver<T> {
+    pub fn new(irs: [&[T]; 2], block_size: usize) -> Self {
+        StereoConvolver {
+            convolvers: irs.map(|ir| Convolver::new(ir, block_size)),
+        }
+    }
+
+    pub fn set_mix(&mut self, wet: T, dry: T) {
+        for convolver in &mut self.convolvers {
+            convolver.wet = wet;
+            convolver.dry = dry;
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        self.convolvers[0].latency()
+    }
+
+    pub fn reset(&mut self) {
+        for convolver in &mut self.convolvers {
+            convolver.reset();
+        }
+    }
+
+    pub fn process(&mut self, inputs: [&[T]; 2], outputs: [&mut [T]; 2]) {
+        for ((convolver, input), output) in self.convolvers.iter_mut().zip(inputs).zip(outputs) {
+            convolver.process(input, output);
+        }
+    }
+}
+
+/// Convolve `buf` with `ir` at once. The output has `buf.len() + ir.len() - 1` samples.
+pub fn convolve<T: Float>(buf: &[T], ir: &[T]) -> Vec<T> {
+    if buf.is_empty() || ir.is_empty() {
+        return vec![];
+    }
+    let len = buf.len() + ir.len() - 1;
+    let block_size = ir.len().next_power_of_two().clamp(64, 4096);
+    let mut convolver = Convolver::new(ir, block_size);
+
+    let mut input = buf.to_vec();
+    input.resize(len + block_size, T::zero());
+    let mut output = vec![T::zero(); input.len()];
+    convolver.process(&input, &mut output);
+    output.drain(..block_size);
+    output
+}
+
+#[test]
+fn test() {
+    let noise = crate::test_util::noise(4000, 1);
+    let (buf, ir) = noise.split_at(3000);
+    let ir: Vec<f64> = ir
+        .iter()
+        .enumerate()
+        .map(|(i, x)| x * (-(i as f64) / 200.0).exp())
+        .collect();
+    let mut expected = vec![0.0; buf.len() + ir.len() - 1];
+    for (i, &x) in buf.iter().enumerate() {
+        for (j, &h) in ir.iter().enumerate() {
+            expected[i + j] += x * h;
+        }
+    }
+    let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9);
+
+    let output = convolve(buf, &ir);
+    assert_eq!(output.len(), expected.len());
+    assert!(close(&output, &expected));
+
+    // Streaming in odd blocks, with the dry signal delayed by the latency.
+    let mut convolver = Convolver::new(&ir, 64);
+    convolver.dry = 0.5;
+    let mut output = vec![0.0; buf.len()];
+    for (input, output) in buf.chunks(37).zip(output.chunks_mut(37)) {
+        convolver.process(input, output);
+    }
+    let expected: Vec<_> = (0..buf.len())
+        .map(|i| {
+            i.checked_sub(64)
+                .map_or(0.0, |i| expected[i] + buf[i] * 0.5)
+        })
+        .collect();
+    assert!(close(&output, &expected));
+
+    // Changing the impulse response.
+    convolver.set_ir(&[1.0]);
+    convolver.reset();
+    convolver.dry = 0.0;
+    let mut output = vec![0.0; 200];
+    convolver.process(&buf[..200], &mut output);
+    assert!(close(&output[64..], &buf[..136]));
+}
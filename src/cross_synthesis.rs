@@ -0,0 +1,224 @@
+//! Cross-synthesis, which imposes the spectral envelope of one signal on another, like a channel vocoder.
+
+use crate::{
+    envelope::{Envelope, EnvelopeBuffers},
+    fft::{expand_half_spectrum, fold_half_spectrum, Fft},
+    num_complex::Complex,
+    num_traits::Zero,
+    windows::{self, WindowError},
+    Float,
+};
+
+/// How `CrossSynthesis` measures the envelopes of the modulator and the carrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Analysis {
+    /// A smooth spectral envelope, e.g. `Envelope::Cepstrum`.
+    Envelope(Envelope),
+    /// The energies of this many bands spaced evenly in log frequency, like a classic channel vocoder.
+    Bands(usize),
+}
+
+/// Takes the spectral envelope of a modulator (e.g. a voice)
+/// and applies it to a carrier (e.g. a synth, noise or another recording), window by window.
+///
+/// The envelope of the carrier is replaced, so the carrier should be rich in all frequencies.
+/// Use it with `transform::transform2()` or `transform::Transformer2`, or through `api::cross_synthesis()`.
+pub struct CrossSynthesis<T: Float> {
+    pub analysis: Analysis,
+    fft: Fft<T>,
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+    modulator_spectrum: Vec<Complex<T>>,
+    carrier_spectrum: Vec<Complex<T>>,
+    envelope_buffers: EnvelopeBuffers<T>,
+    modulator_envelope: Vec<T>,
+    carrier_envelope: Vec<T>,
+}
+
+impl<T: Float> CrossSynthesis<T> {
+    /// Panics if the windows can't reconstruct a signal, see `try_new()`.
+    pub fn new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        analysis: Analysis,
+    ) -> Self {
+        match Self::try_new(pre_window, post_window, slide_size, analysis) {
+            Ok(x) => x,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Same as `new()` but returns an error if the windows can't reconstruct a signal,
+    /// see `windows::check_reconstruction()`.
+    pub fn try_new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        analysis: Analysis,
+    ) -> Result<Self, WindowError> {
+        let post_window = windows::check_reconstruction(&pre_window, &post_window, slide_size)?
+            .normalize(&post_window);
+
+        let window_size = pre_window.len();
+        let fft = Fft::new(window_size);
+        Ok(CrossSynthesis {
+            analysis,
+            pre_window,
+            post_window,
+            slide_size,
+            buffer: vec![T::zero(); window_size],
+            half_spectrum: vec![Complex::zero(); fft.half_len()],
+            scratch: vec![Complex::zero(); fft.scratch_len()],
+            modulator_spectrum: vec![Complex::zero(); window_size],
+            carrier_spectrum: vec![Complex::zero(); window_size],
+            envelope_buffers: EnvelopeBuffers::new(&fft),
+            modulator_envelope: vec![T::zero(); window_size],
+            carrier_envelope: vec![T::zero(); window_size],
+            fft,
+        })
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.pre_window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    /// Process a window. `modulator`, `carrier` and `output` must have the window size.
+    pub fn process(&mut self, modulator: &[T], carrier: &[T], output: &mut [T]) {
+        for (input, spectrum) in [
+            (modulator, &mut self.modulator_spectrum),
+            (carrier, &mut self.carrier_spectrum),
+        ] {
+            for ((b, &x), &w) in self.buffer.iter_mut().zip(input).zip(&self.pre_window) {
+                *b = x * w;
+            }
+            self.fft.forward_real_with_scratch(
+                &mut self.buffer,
+                &mut self.half_spectrum,
+                &mut self.scratch,
+            );
+            expand_half_spectrum(&self.half_spectrum, spectrum);
+        }
+
+        replace_envelope(
+            self.analysis,
+            &self.fft,
+            &mut self.envelope_buffers,
+            [&mut self.modulator_envelope, &mut self.carrier_envelope],
+            &self.modulator_spectrum,
+            &mut self.carrier_spectrum,
+        );
+        fold_half_spectrum(&self.carrier_spectrum, &mut self.half_spectrum);
+        self.fft.inverse_real_with_scratch(
+            &mut self.half_spectrum,
+            &mut self.buffer,
+            &mut self.scratch,
+        );
+        let scale = T::one() / T::from(self.buffer.len()).unwrap();
+        for ((y, &x), &w) in output.iter_mut().zip(&self.buffer).zip(&self.post_window) {
+            *y = x * scale * w;
+        }
+    }
+
+    /// Same as `process()` but returns a new `Vec`.
+    pub fn process_to_vec(&mut self, modulator: &[T], carrier: &[T]) -> Vec<T> {
+        let mut output = vec![T::zero(); modulator.len()];
+        self.process(modulator, carrier, &mut output);
+        output
+    }
+
+    /// Replace the envelope of the spectrum of `carrier` with that of `modulator`.
+    pub fn process_spectrum(&mut self, modulator: &[Complex<T>], carrier: &mut [Complex<T>]) {
+        replace_envelope(
+            self.analysis,
+            &self.fft,
+            &mut self.envelope_buffers,
+            [&mut self.modulator_envelope, &mut self.carrier_envelope],
+            modulator,
+            carrier,
+        );
+    }
+}
+
+fn replace_envelope<T: Float>(
+    analysis: Analysis,
+    fft: &Fft<T>,
+    envelope_buffers: &mut EnvelopeBuffers<T>,
+    envelopes: [&mut [T]; 2],
+    modulator: &[Complex<T>],
+    carrier: &mut [Complex<T>],
+) {
+    let len = carrier.len();
+    match analysis {
+        Analysis::Envelope(envelope) => {
+            let [modulator_envelope, carrier_envelope] = envelopes;
+            envelope.estimate_into(fft, modulator, envelope_buffers, modulator_envelope);
+            envelope.estimate_into(fft, carrier, envelope_buffers, carrier_envelope);
+            for ((x, &m), &c) in carrier
+                .iter_mut()
+                .zip(modulator_envelope.iter())
+                .zip(carrier_envelope.iter())
+            {
+                *x = *x * (m - c).exp();
+            }
+        }
+        Analysis::Bands(bands) => {
+            assert!(0 < bands);
+            let half_len = len / 2 + 1;
+            let mut start = 0;
+            for band in 0..bands {
+                // Edges from bin 1 to the Nyquist frequency, at least a bin apart.
+                let edge = (half_len as f64).powf((band + 1) as f64 / bands as f64);
+                let end = (edge.round() as usize).max(start + 1).min(half_len);
+                let energy = |spectrum: &[Complex<T>]| {
+                    spectrum[start..end]
+                        .iter()
+                        .fold(T::epsilon(), |a, x| a + x.norm_sqr())
+                };
+                let gain = (energy(modulator) / energy(carrier)).sqrt();
+                for x in &mut carrier[start..end] {
+                    *x = *x * gain;
+                }
+                start = end;
+            }
+            for i in half_len..len {
+                carrier[i] = carrier[len - i].conj();
+            }
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let len = 512;
+    let mut synthesis = CrossSynthesis::new(
+        crate::windows::hann_window(len),
+        crate::windows::hann_window(len),
+        len / 4,
+        Analysis::Bands(16),
+    );
+    let carrier = crate::test_util::noise(len, 1);
+
+    // The carrier takes the modulator's envelope, also in level.
+    let modulator: Vec<f64> = carrier.iter().map(|x| x * 0.25).collect();
+    for analysis in [
+        Analysis::Bands(16),
+        Analysis::Envelope(Envelope::Cepstrum(20)),
+    ] {
+        synthesis.analysis = analysis;
+        let output = synthesis.process_to_vec(&modulator, &carrier);
+        let expected = synthesis.process_to_vec(&modulator, &modulator);
+        assert!(output
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+}
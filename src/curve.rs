@@ -0,0 +1,66 @@
+//! Parameters which change over time, for offline processing.
+
+use crate::Float;
+
+/// A value as a function of time in seconds.
+///
+/// Closures of `FnMut(T) -> T` are also curves.
+pub trait Curve<T: Float> {
+    fn value_at(&mut self, time: T) -> T;
+}
+
+impl<T: Float, F: FnMut(T) -> T> Curve<T> for F {
+    fn value_at(&mut self, time: T) -> T {
+        self(time)
+    }
+}
+
+/// A piecewise linear curve through `(time, value)` points.
+///
+/// The value is held before the first point and after the last point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoints<T: Float> {
+    points: Vec<(T, T)>,
+}
+
+impl<T: Float> Breakpoints<T> {
+    /// Panics if `points` is empty or not sorted by time.
+    pub fn new(points: Vec<(T, T)>) -> Self {
+        assert!(!points.is_empty());
+        assert!(points.windows(2).all(|w| w[0].0 <= w[1].0));
+        Breakpoints { points }
+    }
+
+    pub fn points(&self) -> &[(T, T)] {
+        &self.points
+    }
+}
+
+impl<T: Float> Curve<T> for Breakpoints<T> {
+    fn value_at(&mut self, time: T) -> T {
+        let i = self.points.partition_point(|p| p.0 <= time);
+        if i == 0 {
+            return self.points[0].1;
+        }
+        if i == self.points.len() {
+            return self.points[i - 1].1;
+        }
+
+        let (t0, v0) = self.points[i - 1];
+        let (t1, v1) = self.points[i];
+        v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+    }
+}
+
+#[test]
+fn test() {
+    let mut curve = Breakpoints::new(vec![(1.0, 1.0), (2.0, 2.0), (2.0, 0.0), (4.0, 1.0)]);
+    assert_eq!(curve.value_at(0.0), 1.0);
+    assert_eq!(curve.value_at(1.5), 1.5);
+    assert_eq!(curve.value_at(2.0), 0.0);
+    assert_eq!(curve.value_at(3.0), 0.5);
+    assert_eq!(curve.value_at(5.0), 1.0);
+
+    let mut curve = |t: f64| t * 2.0;
+    assert_eq!(curve.value_at(1.5), 3.0);
+}
@@ -0,0 +1,289 @@
+//! Spectral envelope estimation.
+
+use crate::{fft::Fft, lpc::levinson_durbin_into, num_complex::Complex, num_traits::Zero, Float};
+
+/// How to estimate the spectral envelope of a window, e.g. for `voice_change::VoiceChange`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Envelope {
+    /// Cepstral smoothing keeping the quefrencies below the order.
+    /// With a high order it follows the harmonics of low voices,
+    /// and with a low order it flattens the formants.
+    Cepstrum(usize),
+    /// Cepstral smoothing with the order set below the pitch period,
+    /// which is found from the cepstral peak between `min_period` and `max_period` in samples.
+    /// Without a clear peak, the order is set for `min_period`.
+    AdaptiveCepstrum {
+        min_period: usize,
+        max_period: usize,
+    },
+    /// The iterative true envelope, which raises the cepstral smoothing
+    /// until it passes over the harmonic peaks instead of through their average.
+    /// It stops within 2 dB of the peaks or after `iterations` smoothings.
+    TrueEnvelope { order: usize, iterations: usize },
+    /// All-pole model of the given order by linear prediction.
+    Lpc(usize),
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::Cepstrum(64)
+    }
+}
+
+/// Work buffers for `Envelope::estimate_into()`.
+#[derive(Clone)]
+pub struct EnvelopeBuffers<T: Float> {
+    cepstrum: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+    log_amplitude: Vec<T>,
+    coefficients: Vec<T>,
+    reflection: Vec<T>,
+}
+
+impl<T: Float> EnvelopeBuffers<T> {
+    pub fn new(fft: &Fft<T>) -> Self {
+        EnvelopeBuffers {
+            cepstrum: vec![T::zero(); fft.len()],
+            half_spectrum: vec![Complex::zero(); fft.half_len()],
+            scratch: vec![Complex::zero(); fft.scratch_len()],
+            log_amplitude: vec![T::zero(); fft.half_len()],
+            coefficients: Vec::new(),
+            reflection: Vec::new(),
+        }
+    }
+}
+
+impl Envelope {
+    /// Estimate the envelope of `spectrum` as a log-amplitude spectrum, like `voice_change::lift_spectrum()`.
+    pub fn estimate<T: Float>(&self, fft: &Fft<T>, spectrum: &[Complex<T>]) -> Vec<T> {
+        let mut envelope = vec![T::zero(); spectrum.len()];
+        self.estimate_into(fft, spectrum, &mut EnvelopeBuffers::new(fft), &mut envelope);
+        envelope
+    }
+
+    /// Same as `estimate()` but writes into `envelope` using the given buffers.
+    ///
+    /// Only the first `len / 2 + 1` bins of `spectrum` are read, so it must be of a real signal,
+    /// and it can also be just those bins. The rest of `envelope`, if any, is mirrored.
+    /// It does not allocate, except for the first use of an LPC order.
+    pub fn estimate_into<T: Float>(
+        &self,
+        fft: &Fft<T>,
+        spectrum: &[Complex<T>],
+        buffers: &mut EnvelopeBuffers<T>,
+        envelope: &mut [T],
+    ) {
+        let len = fft.len();
+        let half_len = len / 2 + 1;
+        let EnvelopeBuffers {
+            cepstrum,
+            half_spectrum,
+            scratch,
+            log_amplitude,
+            coefficients,
+            reflection,
+        } = buffers;
+
+        for (a, x) in log_amplitude.iter_mut().zip(spectrum) {
+            *a = (x.norm() + T::epsilon()).ln();
+        }
+
+        match *self {
+            Envelope::Cepstrum(order) => {
+                assert!(0 < order && order < len / 2);
+                to_cepstrum(fft, log_amplitude, half_spectrum, scratch, cepstrum);
+                from_cepstrum(fft, cepstrum, order, half_spectrum, scratch, envelope);
+            }
+            Envelope::AdaptiveCepstrum {
+                min_period,
+                max_period,
+            } => {
+                assert!(0 < min_period && min_period <= max_period);
+                to_cepstrum(fft, log_amplitude, half_spectrum, scratch, cepstrum);
+                let scale = T::one() / T::from(len).unwrap();
+                let threshold = T::from(0.05).unwrap();
+                let period = (min_period..=max_period.min(len / 2 - 1))
+                    .map(|q| (q, cepstrum[q] * scale))
+                    .filter(|&(_, c)| threshold < c)
+                    .fold(None, |a: Option<(usize, T)>, b| match a {
+                        Some(a) if b.1 <= a.1 => Some(a),
+                        _ => Some(b),
+                    })
+                    .map_or(min_period, |(q, _)| q);
+                let order = (period * 3 / 4).clamp(1, len / 2 - 1);
+                from_cepstrum(fft, cepstrum, order, half_spectrum, scratch, envelope);
+            }
+            Envelope::TrueEnvelope { order, iterations } => {
+                assert!(0 < order && order < len / 2);
+                // 2 dB in natural log.
+                let threshold = T::from(2.0 / 20.0 * std::f64::consts::LN_10).unwrap();
+                for i in 0..iterations.max(1) {
+                    if 0 < i {
+                        for (a, &e) in log_amplitude.iter_mut().zip(envelope.iter()) {
+                            *a = a.max(e);
+                        }
+                    }
+                    to_cepstrum(fft, log_amplitude, half_spectrum, scratch, cepstrum);
+                    from_cepstrum(fft, cepstrum, order, half_spectrum, scratch, envelope);
+
+                    let below = spectrum
+                        .iter()
+                        .zip(envelope.iter())
+                        .take(half_len)
+                        .fold(T::zero(), |m, (x, &e)| {
+                            m.max((x.norm() + T::epsilon()).ln() - e)
+                        });
+                    if below < threshold {
+                        break;
+                    }
+                }
+            }
+            Envelope::Lpc(order) => {
+                assert!(0 < order && order < len / 2);
+                // The autocorrelation is the inverse transform of the power spectrum.
+                for (c, x) in half_spectrum.iter_mut().zip(spectrum) {
+                    *c = Complex::from(x.norm_sqr());
+                }
+                fft.inverse_real_with_scratch(half_spectrum, cepstrum, scratch);
+                let scale = T::one() / T::from(len).unwrap();
+                for r in cepstrum[..=order].iter_mut() {
+                    *r = *r * scale;
+                }
+
+                coefficients.resize(order + 1, T::zero());
+                reflection.resize(order, T::zero());
+                let Some(error) =
+                    levinson_durbin_into(&cepstrum[..=order], coefficients, reflection)
+                else {
+                    envelope.fill(T::epsilon().ln());
+                    return;
+                };
+
+                // The envelope is sqrt(error) / |A|.
+                cepstrum.fill(T::zero());
+                cepstrum[..=order].copy_from_slice(coefficients);
+                fft.forward_real_with_scratch(cepstrum, half_spectrum, scratch);
+                let gain = error.sqrt().ln();
+                for (e, a) in envelope.iter_mut().zip(half_spectrum.iter()) {
+                    *e = gain - (a.norm() + T::epsilon()).ln();
+                }
+            }
+        }
+
+        for i in half_len..envelope.len() {
+            envelope[i] = envelope[len - i];
+        }
+    }
+}
+
+/// Unnormalized real cepstrum of the log-amplitude half spectrum.
+fn to_cepstrum<T: Float>(
+    fft: &Fft<T>,
+    log_amplitude: &[T],
+    half_spectrum: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    cepstrum: &mut [T],
+) {
+    for (c, &a) in half_spectrum.iter_mut().zip(log_amplitude) {
+        *c = Complex::from(a);
+    }
+    fft.inverse_real_with_scratch(half_spectrum, cepstrum, scratch);
+}
+
+/// Log-amplitude half spectrum of the quefrencies of `cepstrum` below `order`.
+/// `cepstrum` is modified.
+fn from_cepstrum<T: Float>(
+    fft: &Fft<T>,
+    cepstrum: &mut [T],
+    order: usize,
+    half_spectrum: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    envelope: &mut [T],
+) {
+    let len = cepstrum.len();
+    cepstrum[order..len - order + 1].fill(T::zero());
+    fft.forward_real_with_scratch(cepstrum, half_spectrum, scratch);
+    let scale = T::one() / T::from(len).unwrap();
+    for (e, x) in envelope.iter_mut().zip(half_spectrum.iter()) {
+        *e = x.re * scale;
+    }
+}
+
+#[test]
+fn test() {
+    let len = 1024;
+    let fft = Fft::new(len);
+    // A pulse train with a period of 64 samples through a resonance at bin 100.
+    let (radius, angle) = (0.97f64, std::f64::consts::TAU * 100.0 / len as f64);
+    let mut buf = vec![0.0; len];
+    for i in 0..len {
+        let pulse = if i % 64 == 0 { 1.0 } else { 0.0 };
+        let y1 = if 0 < i { buf[i - 1] } else { 0.0 };
+        let y2 = if 1 < i { buf[i - 2] } else { 0.0 };
+        buf[i] = pulse + 2.0 * radius * angle.cos() * y1 - radius * radius * y2;
+    }
+    let window = crate::windows::hann_window::<f64>(len);
+    let mut spectrum: Vec<_> = buf
+        .iter()
+        .zip(&window)
+        .map(|(&x, &w)| Complex::new(x * w, 0.0))
+        .collect();
+    fft.forward(&mut spectrum);
+    let log_amplitude: Vec<_> = spectrum
+        .iter()
+        .map(|x| (x.norm() + f64::EPSILON).ln())
+        .collect();
+    let peak = |envelope: &[f64]| {
+        (1..len / 2)
+            .max_by(|&a, &b| envelope[a].partial_cmp(&envelope[b]).unwrap())
+            .unwrap()
+    };
+
+    // The cepstrum is the same as lifting.
+    let cepstrum = Envelope::Cepstrum(30).estimate(&fft, &spectrum);
+    let lifted = crate::voice_change::lift_spectrum(&fft, &spectrum, |b| {
+        b[30..len - 30 + 1].fill(0.0);
+    });
+    assert!(cepstrum
+        .iter()
+        .zip(&lifted)
+        .all(|(a, b)| (a - b).abs() < 1e-9));
+
+    // The harmonics are at every 16 bins. The adaptive order is below the period.
+    let adaptive = Envelope::AdaptiveCepstrum {
+        min_period: 20,
+        max_period: 200,
+    }
+    .estimate(&fft, &spectrum);
+    assert_eq!(adaptive, Envelope::Cepstrum(48).estimate(&fft, &spectrum));
+
+    // The true envelope goes over the harmonics, unlike the cepstrum.
+    let true_envelope = Envelope::TrueEnvelope {
+        order: 30,
+        iterations: 100,
+    }
+    .estimate(&fft, &spectrum);
+    let harmonics = (16..len / 2).step_by(16);
+    let max_below = |envelope: &[f64]| {
+        harmonics
+            .clone()
+            .fold(0.0f64, |m, i| m.max(log_amplitude[i] - envelope[i]))
+    };
+    assert!(max_below(&true_envelope) < 0.3);
+    assert!(0.5 < max_below(&cepstrum));
+
+    for envelope in [&cepstrum, &adaptive, &true_envelope] {
+        assert!(peak(envelope).abs_diff(100) <= 16);
+    }
+
+    // LPC finds the resonance and is symmetric.
+    let lpc = Envelope::Lpc(12).estimate(&fft, &spectrum);
+    assert!(peak(&lpc).abs_diff(100) <= 4);
+    assert_eq!(lpc[1], lpc[len - 1]);
+    // Silence
+    assert!(Envelope::Lpc(12)
+        .estimate(&fft, &vec![Complex::zero(); len])
+        .iter()
+        .all(|x| x.is_finite()));
+}
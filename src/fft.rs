@@ -1,28 +1,145 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use rustfft::{num_complex::Complex, FftNum};
+use realfft::{ComplexToReal, FftError, RealFftPlanner, RealToComplex};
+use rustfft::{
+    num_complex::Complex,
+    num_traits::{Float, Zero},
+    FftNum,
+};
 
-#[derive(Clone)]
 pub struct Fft<T: FftNum> {
     forward: Arc<dyn rustfft::Fft<T>>,
     inverse: Arc<dyn rustfft::Fft<T>>,
+    real_forward: Arc<dyn RealToComplex<T>>,
+    real_inverse: Arc<dyn ComplexToReal<T>>,
+    /// Scratch for the methods without `_with_scratch`, so that they don't allocate on every call.
+    scratch: Mutex<Vec<Complex<T>>>,
+}
+
+impl<T: FftNum> Clone for Fft<T> {
+    fn clone(&self) -> Self {
+        Self {
+            forward: self.forward.clone(),
+            inverse: self.inverse.clone(),
+            real_forward: self.real_forward.clone(),
+            real_inverse: self.real_inverse.clone(),
+            scratch: Mutex::new(vec![Complex::zero(); self.scratch_len()]),
+        }
+    }
 }
 
 impl<T: FftNum> Fft<T> {
     pub fn new(size: usize) -> Self {
         let mut planner = rustfft::FftPlanner::new();
-        Self {
+        let mut real_planner = RealFftPlanner::new();
+        let mut fft = Self {
             forward: planner.plan_fft_forward(size),
             inverse: planner.plan_fft_inverse(size),
-        }
+            real_forward: real_planner.plan_fft_forward(size),
+            real_inverse: real_planner.plan_fft_inverse(size),
+            scratch: Mutex::new(vec![]),
+        };
+        fft.scratch = Mutex::new(vec![Complex::zero(); fft.scratch_len()]);
+        fft
+    }
+
+    pub fn len(&self) -> usize {
+        self.forward.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bins of a real signal's spectrum, `len / 2 + 1`.
+    pub fn half_len(&self) -> usize {
+        self.len() / 2 + 1
+    }
+
+    pub fn forward(&self, buffer: &mut [Complex<T>]) {
+        self.with_scratch(|scratch| self.forward_with_scratch(buffer, scratch));
+    }
+
+    pub fn inverse(&self, buffer: &mut [Complex<T>]) {
+        self.with_scratch(|scratch| self.inverse_with_scratch(buffer, scratch));
+    }
+
+    /// Length of the scratch buffer required by `*_with_scratch()`.
+    pub fn scratch_len(&self) -> usize {
+        self.forward
+            .get_inplace_scratch_len()
+            .max(self.inverse.get_inplace_scratch_len())
+            .max(self.real_forward.get_scratch_len())
+            .max(self.real_inverse.get_scratch_len())
+    }
+
+    /// Same as `forward()` but does not allocate.
+    pub fn forward_with_scratch(&self, buffer: &mut [Complex<T>], scratch: &mut [Complex<T>]) {
+        self.forward.process_with_scratch(buffer, scratch);
+    }
+
+    /// Same as `inverse()` but does not allocate.
+    pub fn inverse_with_scratch(&self, buffer: &mut [Complex<T>], scratch: &mut [Complex<T>]) {
+        self.inverse.process_with_scratch(buffer, scratch);
+    }
+
+    /// Forward transform of a real signal into its `len / 2 + 1` bins.
+    /// `buffer` is used as a work area and its content is destroyed.
+    pub fn forward_real(&self, buffer: &mut [T], spectrum: &mut [Complex<T>]) {
+        self.with_scratch(|scratch| self.forward_real_with_scratch(buffer, spectrum, scratch));
     }
 
-    pub fn forward(&self, buffer: &mut Vec<Complex<T>>) {
-        self.forward.process(buffer);
+    /// Inverse transform of `len / 2 + 1` bins into a real signal, without normalization.
+    /// `spectrum` is used as a work area and its content is destroyed.
+    pub fn inverse_real(&self, spectrum: &mut [Complex<T>], buffer: &mut [T]) {
+        self.with_scratch(|scratch| self.inverse_real_with_scratch(spectrum, buffer, scratch));
     }
 
-    pub fn inverse(&self, buffer: &mut Vec<Complex<T>>) {
-        self.inverse.process(buffer);
+    /// Same as `forward_real()` but does not allocate.
+    pub fn forward_real_with_scratch(
+        &self,
+        buffer: &mut [T],
+        spectrum: &mut [Complex<T>],
+        scratch: &mut [Complex<T>],
+    ) {
+        let scratch_len = self.real_forward.get_scratch_len();
+        check(self.real_forward.process_with_scratch(
+            buffer,
+            spectrum,
+            &mut scratch[..scratch_len],
+        ));
+    }
+
+    /// Same as `inverse_real()` but does not allocate.
+    pub fn inverse_real_with_scratch(
+        &self,
+        spectrum: &mut [Complex<T>],
+        buffer: &mut [T],
+        scratch: &mut [Complex<T>],
+    ) {
+        let scratch_len = self.real_inverse.get_scratch_len();
+        check(self.real_inverse.process_with_scratch(
+            spectrum,
+            buffer,
+            &mut scratch[..scratch_len],
+        ));
+    }
+
+    /// Run `f` with the cached scratch,
+    /// or with a new one if another thread is using it at the same time.
+    fn with_scratch(&self, f: impl FnOnce(&mut [Complex<T>])) {
+        match self.scratch.try_lock() {
+            Ok(mut scratch) => f(&mut scratch),
+            Err(_) => f(&mut vec![Complex::zero(); self.scratch_len()]),
+        }
+    }
+}
+
+fn check(result: Result<(), FftError>) {
+    match result {
+        // Non-zero imaginary parts of DC / Nyquist bins are just ignored.
+        Ok(()) | Err(FftError::InputValues(..)) => {}
+        Err(err) => panic!("{}", err),
     }
 }
 
@@ -40,3 +157,59 @@ pub fn fill_right_part_of_spectrum<T: FftNum>(spectrum: &mut [Complex<T>]) {
         spectrum[len - i] = spectrum[i].conj();
     }
 }
+
+/// Expand `len / 2 + 1` bins of a real signal into the full spectrum.
+pub fn expand_half_spectrum<T: FftNum>(half: &[Complex<T>], spectrum: &mut [Complex<T>]) {
+    let (left, right) = spectrum.split_at_mut(half.len());
+
+    left.copy_from_slice(half);
+    for (x, y) in right.iter_mut().rev().zip(&half[1..]) {
+        *x = y.conj();
+    }
+}
+
+/// Take the `len / 2 + 1` bins from a full spectrum.
+///
+/// The spectrum is made Hermitian first,
+/// so the result equals the real part of the inverse transform of `spectrum`.
+pub fn fold_half_spectrum<T: FftNum + Float>(spectrum: &[Complex<T>], half: &mut [Complex<T>]) {
+    let half_scale = T::one() / (T::one() + T::one());
+
+    half[0] = Complex::from(spectrum[0].re);
+    for ((x, y), z) in half[1..]
+        .iter_mut()
+        .zip(&spectrum[1..])
+        .zip(spectrum[1..].iter().rev())
+    {
+        *x = (y + z.conj()) * half_scale;
+    }
+}
+
+#[test]
+fn test_real() {
+    let len = 64;
+    let fft = Fft::<f64>::new(len);
+    let buf: Vec<f64> = (0..len).map(|i| (i as f64 * 0.3).sin() + 0.1).collect();
+
+    let mut spectrum: Vec<_> = buf.iter().copied().map(Complex::from).collect();
+    fft.forward(&mut spectrum);
+
+    let mut half_spectrum = vec![Complex::from(0.0); fft.half_len()];
+    fft.forward_real(&mut buf.clone(), &mut half_spectrum);
+    for (x, y) in half_spectrum.iter().zip(&spectrum) {
+        assert!((x - y).norm() < 1e-9);
+    }
+
+    let mut expanded = vec![Complex::from(0.0); len];
+    expand_half_spectrum(&half_spectrum, &mut expanded);
+    for (x, y) in expanded.iter().zip(&spectrum) {
+        assert!((x - y).norm() < 1e-9);
+    }
+
+    let mut output = vec![0.0; len];
+    fold_half_spectrum(&expanded, &mut half_spectrum);
+    fft.inverse_real(&mut half_spectrum, &mut output);
+    for (x, y) in output.iter().zip(&buf) {
+        assert!((x / len as f64 - y).abs() < 1e-9);
+    }
+}
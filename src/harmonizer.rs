@@ -0,0 +1,260 @@
+//! Pitch-shifted copies of a voice mixed with the original.
+
+use crate::{
+    auto_tune::Scale,
+    envelope::Envelope,
+    fft::Fft,
+    num_complex::Complex,
+    num_traits::Zero,
+    pitch_detection::PitchDetector,
+    pitch_tracker::PitchTracker,
+    spectral::SpectralProcessor,
+    voice_change::VoiceChange,
+    windows::{self, WindowError},
+    Float,
+};
+
+/// The interval of a harmony voice from the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interval<T> {
+    /// A fixed interval, which does not need the pitch of the input.
+    Semitones(T),
+    /// Steps of the scale of the `Harmonizer` from the scale note nearest to the input,
+    /// e.g. -2 for a third below.
+    Degrees(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Voice<T> {
+    pub interval: Interval<T>,
+    pub gain: T,
+    /// From -1 (left) to 1 (right). Ignored for mono output.
+    pub pan: T,
+    /// Keep the formants of the input, or shift them with the pitch.
+    pub preserve_formant: bool,
+}
+
+impl<T: Float> Voice<T> {
+    /// A centered voice with unit gain that preserves the formants.
+    pub fn new(interval: Interval<T>) -> Self {
+        Voice {
+            interval,
+            gain: T::one(),
+            pan: T::zero(),
+            preserve_formant: true,
+        }
+    }
+}
+
+/// Mixes harmony voices with the input, window by window.
+///
+/// The pitch is tracked once per window and the spectrum of the input is shared by all the voices.
+/// The output is mono or stereo.
+pub struct Harmonizer<T: Float, D: PitchDetector<T>> {
+    /// Root note of the scale for `Interval::Degrees`, 0 for C to 11 for B.
+    pub key: usize,
+    pub scale: Scale,
+    /// Frequency of A4 in Hz.
+    pub reference: T,
+    /// Gain of the input in the mix.
+    pub dry: T,
+    /// The estimator of the formants. The cepstral order should be below the wavelength of the input.
+    pub envelope: Envelope,
+    voices: Vec<Voice<T>>,
+    processors: Vec<VoiceChange<T>>,
+    /// Latest pitch ratios, held over unvoiced windows.
+    pitches: Vec<T>,
+    tracker: PitchTracker<T, D>,
+    fft: Fft<T>,
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    /// Work buffer of `SpectralProcessor::process_half()`.
+    spectrum: Vec<Complex<T>>,
+    voice_spectrum: Vec<Complex<T>>,
+    /// Half spectra of the left and right outputs.
+    mixes: [Vec<Complex<T>>; 2],
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: Float, D: PitchDetector<T>> Harmonizer<T, D> {
+    /// The key is C major.
+    /// Panics if the windows can't reconstruct a signal, see `try_new()`.
+    pub fn new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        tracker: PitchTracker<T, D>,
+        voices: Vec<Voice<T>>,
+    ) -> Self {
+        match Self::try_new(pre_window, post_window, slide_size, tracker, voices) {
+            Ok(x) => x,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Same as `new()` but returns an error if the windows can't reconstruct a signal,
+    /// see `windows::check_reconstruction()`.
+    pub fn try_new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        tracker: PitchTracker<T, D>,
+        voices: Vec<Voice<T>>,
+    ) -> Result<Self, WindowError> {
+        let post_window = windows::check_reconstruction(&pre_window, &post_window, slide_size)?
+            .normalize(&post_window);
+
+        let window_size = pre_window.len();
+        let fft = Fft::new(window_size);
+        let processors = voices
+            .iter()
+            .map(|_| VoiceChange::new(window_size, slide_size, window_size / 8, T::one(), T::one()))
+            .collect();
+        let scratch = vec![Complex::zero(); fft.scratch_len()];
+        let half_len = fft.half_len();
+        Ok(Harmonizer {
+            key: 0,
+            scale: Scale::Major,
+            reference: T::from(440.0).unwrap(),
+            dry: T::one(),
+            envelope: Envelope::Cepstrum(window_size / 8),
+            pitches: vec![T::one(); voices.len()],
+            voices,
+            processors,
+            tracker,
+            fft,
+            pre_window,
+            post_window,
+            slide_size,
+            buffer: vec![T::zero(); window_size],
+            half_spectrum: vec![Complex::zero(); half_len],
+            spectrum: vec![Complex::zero(); window_size],
+            voice_spectrum: vec![Complex::zero(); half_len],
+            mixes: [
+                vec![Complex::zero(); half_len],
+                vec![Complex::zero(); half_len],
+            ],
+            scratch,
+        })
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.pre_window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    pub fn voices(&self) -> &[Voice<T>] {
+        &self.voices
+    }
+
+    pub fn voices_mut(&mut self) -> &mut [Voice<T>] {
+        &mut self.voices
+    }
+
+    pub fn tracker_mut(&mut self) -> &mut PitchTracker<T, D> {
+        &mut self.tracker
+    }
+
+    pub fn reset(&mut self) {
+        self.tracker.reset();
+        for processor in &mut self.processors {
+            processor.reset();
+        }
+        self.pitches.fill(T::one());
+    }
+
+    /// Process a window. `input` and each of `outputs` must have the window size.
+    /// `outputs` has one slice for mono or two for stereo.
+    pub fn process(&mut self, input: &[T], outputs: &mut [&mut [T]]) {
+        assert!(outputs.len() == 1 || outputs.len() == 2);
+        assert_eq!(
+            input.len(),
+            self.window_size(),
+            "the input must have the window size"
+        );
+        assert!(
+            outputs
+                .iter()
+                .all(|output| output.len() == self.window_size()),
+            "the outputs must have the window size"
+        );
+        let stereo = outputs.len() == 2;
+
+        let record = self.tracker.process(input);
+        let note = record.voiced.then(|| {
+            (record.frequency / self.reference).log2() * T::from(12).unwrap()
+                + T::from(69).unwrap()
+        });
+
+        for ((b, &x), &w) in self.buffer.iter_mut().zip(input).zip(&self.pre_window) {
+            *b = x * w;
+        }
+        self.fft.forward_real_with_scratch(
+            &mut self.buffer,
+            &mut self.half_spectrum,
+            &mut self.scratch,
+        );
+
+        for mix in &mut self.mixes {
+            for (y, &x) in mix.iter_mut().zip(&self.half_spectrum) {
+                *y = x * self.dry;
+            }
+        }
+
+        for (i, voice) in self.voices.iter().enumerate() {
+            let semitones = match voice.interval {
+                Interval::Semitones(semitones) => Some(semitones),
+                Interval::Degrees(degrees) => note.and_then(|note| {
+                    let nearest = self.scale.nearest(self.key, note)?.to_i64().unwrap();
+                    let target = self.scale.step(self.key, nearest, degrees);
+                    Some(T::from(target).unwrap() - note)
+                }),
+            };
+            if let Some(semitones) = semitones {
+                self.pitches[i] = (semitones / T::from(12).unwrap()).exp2();
+            }
+
+            let processor = &mut self.processors[i];
+            processor.envelope = self.envelope;
+            processor.pitch = self.pitches[i];
+            processor.formant = if voice.preserve_formant {
+                T::one()
+            } else {
+                self.pitches[i]
+            };
+            self.voice_spectrum.copy_from_slice(&self.half_spectrum);
+            processor.process_half(&mut self.voice_spectrum, &mut self.spectrum);
+
+            // Balance panning, which keeps a centered voice at its gain.
+            let pan = voice.pan.max(-T::one()).min(T::one());
+            let gains = if stereo {
+                [
+                    voice.gain * (T::one() - pan).min(T::one()),
+                    voice.gain * (T::one() + pan).min(T::one()),
+                ]
+            } else {
+                [voice.gain, T::zero()]
+            };
+            for (mix, gain) in self.mixes.iter_mut().zip(gains) {
+                for (y, &x) in mix.iter_mut().zip(&self.voice_spectrum) {
+                    *y = *y + x * gain;
+                }
+            }
+        }
+
+        let scale = T::one() / T::from(self.buffer.len()).unwrap();
+        for (output, mix) in outputs.iter_mut().zip(&mut self.mixes) {
+            self.fft
+                .inverse_real_with_scratch(mix, &mut self.buffer, &mut self.scratch);
+            for ((y, &x), &w) in output.iter_mut().zip(&self.buffer).zip(&self.post_window) {
+                *y = x * scale * w;
+            }
+        }
+    }
+}
@@ -1,9 +1,28 @@
 pub mod api;
+pub mod auto_tune;
+pub mod convolution;
+pub mod cross_synthesis;
+pub mod curve;
+pub mod envelope;
 pub mod fft;
 pub mod float;
+pub mod harmonizer;
+pub mod lpc;
+#[cfg(feature = "midi")]
+pub mod midi;
 pub mod overlapping_flatten;
+pub mod phase_reconstruction;
 pub mod pitch_detection;
 pub mod pitch_shift;
+pub mod pitch_tracker;
+pub mod resample;
+pub mod ring_buffer;
+pub mod sola;
+pub mod spectral;
+pub mod stft;
+#[cfg(test)]
+mod test_util;
+pub mod time_stretch;
 pub mod transform;
 pub mod voice_change;
 pub mod windows;
@@ -18,26 +37,8 @@ pub fn apply_window<'a, T: rustfft::num_traits::Float>(
     iter.zip(window.iter()).map(|(x, &y)| x * y)
 }
 
-/// Resample with quadratic interpolation.
+/// Resample `buf` to `buf.len() * rate` samples, as if played `rate` times slower.
+/// See `resample::Resampler` for streaming and other qualities.
 pub fn resample<T: float::Float>(buf: &[T], rate: T) -> Vec<T> {
-    let mut output = vec![
-        T::zero();
-        (T::from(buf.len()).unwrap() * rate)
-            .ceil()
-            .to_usize()
-            .unwrap()
-    ];
-
-    for i in 0..output.len() {
-        let p = T::from(i).unwrap() / rate;
-        let j = p.to_usize().unwrap();
-
-        let x = buf[j];
-        let y = buf.get(j + 1).copied().unwrap_or(T::zero());
-        let z = buf.get(j + 2).copied().unwrap_or(T::zero());
-
-        output[i] = y - (x - z) * p.fract() / T::from(4.0).unwrap();
-    }
-
-    output
+    resample::resample(buf, rate, resample::Quality::default())
 }
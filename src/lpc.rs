@@ -0,0 +1,304 @@
+//! Linear predictive coding: analysis, conversions and filtering.
+//!
+//! The predictor polynomial is `A(z) = a[0] + a[1] z^-1 + ... + a[p] z^-p` with `a[0] = 1`,
+//! which models a signal as white noise through the all-pole filter `1 / A(z)`.
+
+use std::f64::consts::PI;
+
+use crate::Float;
+
+/// Result of `analyze()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lpc<T> {
+    /// The predictor polynomial, `order + 1` coefficients from `a[0] = 1`.
+    pub coefficients: Vec<T>,
+    /// The reflection (PARCOR) coefficients, `order` of them.
+    pub reflection: Vec<T>,
+    /// Energy of the prediction error, so the gain of the all-pole filter is its square root.
+    pub error: T,
+}
+
+/// Autocorrelation of `buf` multiplied by `window`, from lag 0 to `order`.
+pub fn autocorrelation<T: Float>(buf: &[T], window: &[T], order: usize) -> Vec<T> {
+    assert_eq!(buf.len(), window.len());
+    let windowed: Vec<T> = buf.iter().zip(window).map(|(&x, &w)| x * w).collect();
+    (0..=order)
+        .map(|lag| {
+            windowed
+                .iter()
+                .zip(windowed.iter().skip(lag))
+                .fold(T::zero(), |a, (&x, &y)| a + x * y)
+        })
+        .collect()
+}
+
+/// Solve the normal equations of linear prediction from the autocorrelation `r`
+/// by the Levinson-Durbin recursion. The order is `r.len() - 1`.
+///
+/// Returns `None` if `r` is of silence or not positive definite.
+pub fn levinson_durbin<T: Float>(r: &[T]) -> Option<Lpc<T>> {
+    let mut coefficients = vec![T::zero(); r.len()];
+    let mut reflection = vec![T::zero(); r.len() - 1];
+    let error = levinson_durbin_into(r, &mut coefficients, &mut reflection)?;
+    Some(Lpc {
+        coefficients,
+        reflection,
+        error,
+    })
+}
+
+/// Same as `levinson_durbin()` but writes into `coefficients` of the length of `r`
+/// and `reflection` one shorter, and returns the prediction error.
+pub fn levinson_durbin_into<T: Float>(
+    r: &[T],
+    coefficients: &mut [T],
+    reflection: &mut [T],
+) -> Option<T> {
+    let a = coefficients;
+    if r[0] <= T::zero() {
+        return None;
+    }
+    a.fill(T::zero());
+    a[0] = T::one();
+    let mut error = r[0];
+    for i in 1..r.len() {
+        let k = -(0..i).fold(T::zero(), |s, j| s + a[j] * r[i - j]) / error;
+        // a[j] += k * a[i - j] for 0 < j < i, in place by pairs.
+        for j in 1..=i / 2 {
+            let (x, y) = (a[j], a[i - j]);
+            a[j] = x + k * y;
+            a[i - j] = y + k * x;
+        }
+        a[i] = k;
+        reflection[i - 1] = k;
+        error = error * (T::one() - k * k);
+        if error <= T::zero() {
+            return None;
+        }
+    }
+    Some(error)
+}
+
+/// Linear prediction of `order` of `buf` multiplied by `window`, e.g. `windows::hann_window()`.
+pub fn analyze<T: Float>(buf: &[T], window: &[T], order: usize) -> Option<Lpc<T>> {
+    levinson_durbin(&autocorrelation(buf, window, order))
+}
+
+/// The predictor polynomial of the reflection coefficients (step-up).
+pub fn reflection_to_coefficients<T: Float>(reflection: &[T]) -> Vec<T> {
+    let mut a = vec![T::zero(); reflection.len() + 1];
+    a[0] = T::one();
+    for (i, &k) in reflection.iter().enumerate() {
+        let i = i + 1;
+        for j in 1..=i / 2 {
+            let (x, y) = (a[j], a[i - j]);
+            a[j] = x + k * y;
+            a[i - j] = y + k * x;
+        }
+        a[i] = k;
+    }
+    a
+}
+
+/// The reflection coefficients of the predictor polynomial (step-down).
+///
+/// Returns `None` if the all-pole filter is unstable, that is, some reflection coefficient is not within (-1, 1).
+pub fn coefficients_to_reflection<T: Float>(coefficients: &[T]) -> Option<Vec<T>> {
+    let mut a = coefficients.to_vec();
+    let mut reflection = vec![T::zero(); a.len() - 1];
+    for i in (1..a.len()).rev() {
+        let k = a[i];
+        if T::one() <= k.abs() {
+            return None;
+        }
+        reflection[i - 1] = k;
+        let d = T::one() - k * k;
+        for j in 1..=i / 2 {
+            let (x, y) = (a[j], a[i - j]);
+            a[j] = (x - k * y) / d;
+            a[i - j] = (y - k * x) / d;
+        }
+    }
+    Some(reflection)
+}
+
+/// Line spectral frequencies of the predictor polynomial,
+/// ascending angular frequencies in (0, π).
+///
+/// Returns `None` if they are not found, which happens if the all-pole filter is unstable.
+pub fn coefficients_to_lsf<T: Float>(coefficients: &[T]) -> Option<Vec<T>> {
+    let order = coefficients.len() - 1;
+    let a: Vec<f64> = coefficients.iter().map(|x| x.to_f64().unwrap()).collect();
+    // P(z) = A(z) + z^-(p+1) A(1/z) and Q(z) = A(z) - z^-(p+1) A(1/z).
+    // Their roots are on the unit circle, alternating from P.
+    let at = |i: usize| a.get(i).copied().unwrap_or(0.0);
+    let p: Vec<f64> = (0..=order + 1).map(|i| at(i) + at(order + 1 - i)).collect();
+    let q: Vec<f64> = (0..=order + 1).map(|i| at(i) - at(order + 1 - i)).collect();
+    // Real-valued P and Q on the unit circle, without the linear phase.
+    let center = (order + 1) as f64 / 2.0;
+    let p_at = |w: f64| {
+        p.iter()
+            .enumerate()
+            .fold(0.0, |s, (i, &c)| s + c * (w * (i as f64 - center)).cos())
+    };
+    let q_at = |w: f64| {
+        q.iter()
+            .enumerate()
+            .fold(0.0, |s, (i, &c)| s + c * (w * (center - i as f64)).sin())
+    };
+
+    let grid = 64 * (order + 1);
+    let mut lsf = Vec::with_capacity(order);
+    for f in [&p_at as &dyn Fn(f64) -> f64, &q_at] {
+        let w_at = |i: usize| PI * (i as f64 + 0.5) / grid as f64;
+        for i in 0..grid - 1 {
+            let (mut lo, mut hi) = (w_at(i), w_at(i + 1));
+            let (mut f_lo, f_hi) = (f(lo), f(hi));
+            if f_lo == 0.0 {
+                lsf.push(lo);
+                continue;
+            }
+            if 0.0 < f_lo * f_hi {
+                continue;
+            }
+            for _ in 0..60 {
+                let mid = (lo + hi) / 2.0;
+                let f_mid = f(mid);
+                if 0.0 < f_lo * f_mid {
+                    lo = mid;
+                    f_lo = f_mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            lsf.push((lo + hi) / 2.0);
+        }
+    }
+    if lsf.len() != order {
+        return None;
+    }
+    lsf.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Some(lsf.into_iter().map(|w| T::from(w).unwrap()).collect())
+}
+
+/// The predictor polynomial of ascending line spectral frequencies.
+pub fn lsf_to_coefficients<T: Float>(lsf: &[T]) -> Vec<T> {
+    let order = lsf.len();
+    let multiply = |poly: &mut Vec<f64>, factor: &[f64]| {
+        let mut product = vec![0.0; poly.len() + factor.len() - 1];
+        for (i, &x) in poly.iter().enumerate() {
+            for (j, &y) in factor.iter().enumerate() {
+                product[i + j] += x * y;
+            }
+        }
+        *poly = product;
+    };
+
+    // The trivial roots at z = -1 and z = 1.
+    let (mut p, mut q) = if order.is_multiple_of(2) {
+        (vec![1.0, 1.0], vec![1.0, -1.0])
+    } else {
+        (vec![1.0], vec![1.0, 0.0, -1.0])
+    };
+    for (i, w) in lsf.iter().enumerate() {
+        let factor = [1.0, -2.0 * w.to_f64().unwrap().cos(), 1.0];
+        if i.is_multiple_of(2) {
+            multiply(&mut p, &factor);
+        } else {
+            multiply(&mut q, &factor);
+        }
+    }
+
+    (0..=order)
+        .map(|i| T::from((p[i] + q[i]) / 2.0).unwrap())
+        .collect()
+}
+
+/// Filter `buf` in place by `A(z)` into the prediction error (residual).
+///
+/// `history` keeps the last `coefficients.len() - 1` inputs, newest first, across blocks.
+/// Start it with zeros.
+pub fn inverse_filter<T: Float>(coefficients: &[T], history: &mut [T], buf: &mut [T]) {
+    assert_eq!(history.len() + 1, coefficients.len());
+    for x in buf {
+        let input = *x;
+        *x = coefficients[1..]
+            .iter()
+            .zip(history.iter())
+            .fold(input, |a, (&c, &h)| a + c * h);
+        if !history.is_empty() {
+            history.copy_within(..history.len() - 1, 1);
+            history[0] = input;
+        }
+    }
+}
+
+/// Filter `buf` in place by the all-pole filter `1 / A(z)`, e.g. to resynthesize from a residual.
+///
+/// `history` keeps the last `coefficients.len() - 1` outputs, newest first, across blocks.
+/// Start it with zeros.
+pub fn synthesis_filter<T: Float>(coefficients: &[T], history: &mut [T], buf: &mut [T]) {
+    assert_eq!(history.len() + 1, coefficients.len());
+    for x in buf {
+        *x = coefficients[1..]
+            .iter()
+            .zip(history.iter())
+            .fold(*x, |a, (&c, &h)| a - c * h);
+        if !history.is_empty() {
+            history.copy_within(..history.len() - 1, 1);
+            history[0] = *x;
+        }
+    }
+}
+
+#[test]
+fn test() {
+    // AR(2) noise with poles at 0.9 e^{±0.3πj}.
+    let expected = [1.0, -2.0 * 0.9 * (0.3 * PI).cos(), 0.81];
+    let mut buf = crate::test_util::noise(4096, 1);
+    let excitation = buf.clone();
+    synthesis_filter(&expected, &mut [0.0; 2], &mut buf);
+
+    let lpc = analyze(&buf, &crate::windows::hann_window(buf.len()), 2).unwrap();
+    for (a, b) in lpc.coefficients.iter().zip(expected) {
+        assert!((a - b).abs() < 0.05);
+    }
+
+    // Conversions round-trip.
+    let reflection = coefficients_to_reflection(&lpc.coefficients).unwrap();
+    for (a, b) in reflection.iter().zip(&lpc.reflection) {
+        assert!((a - b).abs() < 1e-9);
+    }
+    let coefficients = reflection_to_coefficients(&lpc.reflection);
+    for (a, b) in coefficients.iter().zip(&lpc.coefficients) {
+        assert!((a - b).abs() < 1e-9);
+    }
+    assert_eq!(coefficients_to_reflection(&[1.0, 0.0, 1.5]), None);
+
+    let speech_like = analyze(&buf, &crate::windows::hann_window(buf.len()), 9).unwrap();
+    for lpc in [&lpc, &speech_like] {
+        let lsf = coefficients_to_lsf(&lpc.coefficients).unwrap();
+        assert!(lsf.windows(2).all(|w| w[0] < w[1]));
+        assert!(0.0 < lsf[0] && lsf[lsf.len() - 1] < PI);
+        let coefficients = lsf_to_coefficients(&lsf);
+        for (a, b) in coefficients.iter().zip(&lpc.coefficients) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    // Inverse filtering in blocks recovers the excitation, and synthesis restores the signal.
+    let mut residual = buf.clone();
+    let mut history = [0.0; 2];
+    for block in residual.chunks_mut(100) {
+        inverse_filter(&expected, &mut history, block);
+    }
+    assert!(residual
+        .iter()
+        .zip(&excitation)
+        .all(|(a, b)| (a - b).abs() < 1e-9));
+    let mut history = [0.0; 2];
+    for block in residual.chunks_mut(77) {
+        synthesis_filter(&expected, &mut history, block);
+    }
+    assert!(residual.iter().zip(&buf).all(|(a, b)| (a - b).abs() < 1e-9));
+}
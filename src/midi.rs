@@ -0,0 +1,98 @@
+//! Reading note events from Standard MIDI Files. Enabled by the `midi` feature.
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::{auto_tune::NoteEvent, Float};
+
+pub use midly::Error;
+
+/// Read the note on/off events of all the tracks of a Standard MIDI File, sorted by time.
+///
+/// Only the events of `channel` (0 to 15) are read if it is given.
+/// The times are in seconds, following the tempo changes.
+pub fn read_notes<T: Float>(smf: &[u8], channel: Option<u8>) -> Result<Vec<NoteEvent<T>>, Error> {
+    let smf = Smf::parse(smf)?;
+
+    // Merge the tracks by absolute ticks. The sort is stable, so the track order is kept.
+    let mut events = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            events.push((tick, event.kind));
+        }
+    }
+    events.sort_by_key(|e| e.0);
+
+    // Seconds per tick. Metrical timing starts at 120 BPM.
+    let (ticks_per_beat, mut tick_length) = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => {
+            let ticks_per_beat = ticks_per_beat.as_int() as f64;
+            (Some(ticks_per_beat), 0.5 / ticks_per_beat)
+        }
+        Timing::Timecode(fps, subframes) => (None, 1.0 / fps.as_f32() as f64 / subframes as f64),
+    };
+
+    let mut notes = Vec::new();
+    let mut time = 0.0;
+    let mut last_tick = 0;
+    for (tick, kind) in events {
+        time += (tick - last_tick) as f64 * tick_length;
+        last_tick = tick;
+
+        match kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(micros_per_beat)) => {
+                if let Some(ticks_per_beat) = ticks_per_beat {
+                    tick_length = micros_per_beat.as_int() as f64 / 1e6 / ticks_per_beat;
+                }
+            }
+            TrackEventKind::Midi { channel: c, message } => {
+                if channel.is_some_and(|channel| channel != c.as_int()) {
+                    continue;
+                }
+                let (note, on) = match message {
+                    MidiMessage::NoteOn { key, vel } => (key.as_int(), 0 < vel.as_int()),
+                    MidiMessage::NoteOff { key, .. } => (key.as_int(), false),
+                    _ => continue,
+                };
+                notes.push(NoteEvent {
+                    time: T::from(time).unwrap(),
+                    note,
+                    on,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(notes)
+}
+
+#[test]
+fn test() {
+    // Format 0, 96 ticks per beat.
+    // A4 for a beat at 120 BPM, then C5 for a beat at 60 BPM, the latter on channel 1.
+    let mut smf = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+    let track = [
+        &b"\x00\x90\x45\x40"[..],
+        b"\x60\x80\x45\x40",
+        b"\x00\xff\x51\x03\x0f\x42\x40",
+        b"\x00\x91\x48\x40",
+        b"\x60\x91\x48\x00",
+        b"\x00\xff\x2f\x00",
+    ]
+    .concat();
+    smf.extend_from_slice(b"MTrk");
+    smf.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    smf.extend_from_slice(&track);
+
+    let notes = read_notes::<f64>(&smf, None).unwrap();
+    let expected = [(0.0, 69, true), (0.5, 69, false), (0.5, 72, true), (1.5, 72, false)];
+    assert_eq!(notes.len(), expected.len());
+    for (note, (time, key, on)) in notes.iter().zip(expected) {
+        assert!((note.time - time).abs() < 1e-9);
+        assert_eq!((note.note, note.on), (key, on));
+    }
+
+    let notes = read_notes::<f64>(&smf, Some(0)).unwrap();
+    assert_eq!(notes.len(), 2);
+}
@@ -110,11 +110,11 @@ pub fn buffer_overlapping_write<T: std::ops::Add<T, Output = T> + Clone>(
     assert!(overlap_size <= buffer.len());
 
     let len = buffer.len();
-    for i in len - overlap_size..len {
+    for y in &mut buffer[len - overlap_size..] {
         let Some(x) = other.next() else {
             return;
         };
-        buffer[i] = buffer[i].clone() + x;
+        *y = y.clone() + x;
     }
     buffer.extend(other);
 }
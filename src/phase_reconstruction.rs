@@ -0,0 +1,314 @@
+//! Phase reconstruction from the magnitudes of a spectrogram,
+//! e.g. after editing them or from a model that only predicts magnitudes.
+
+use std::f64::consts::TAU;
+
+use crate::{
+    num_complex::Complex,
+    num_traits::Zero,
+    stft::{istft, normalize, Spectrogram, Stft},
+    Float,
+};
+
+/// The Griffin-Lim algorithm, which alternates between the signal and the spectrogram
+/// to find phases consistent with the magnitudes of `spectrogram`.
+///
+/// The phases of `spectrogram` are first set to advance by the center frequency of each bin, like a phase vocoder,
+/// and are replaced with the reconstructed ones. Returns the signal as `stft::istft()`.
+///
+/// # Example
+/// ```
+/// # use voiche::{phase_reconstruction::griffin_lim, stft::Spectrogram, windows::hann_window};
+/// let mut spectrogram = Spectrogram::new(hann_window(1024), 256, 4096);
+/// // e.g. from a model
+/// let magnitudes = vec![vec![1.0f32; spectrogram.bins()]; spectrogram.frames.len()];
+/// spectrogram.set_magnitudes(&magnitudes);
+/// let output = griffin_lim(&mut spectrogram, 32);
+/// assert_eq!(output.len(), 4096);
+/// ```
+pub fn griffin_lim<T: Float>(spectrogram: &mut Spectrogram<T>, iterations: usize) -> Vec<T> {
+    fast_griffin_lim(spectrogram, iterations, T::zero())
+}
+
+/// The fast Griffin-Lim algorithm, which extrapolates each iteration by `momentum`, e.g. 0.99,
+/// and converges in fewer iterations than `griffin_lim()`, which is the same with 0.
+pub fn fast_griffin_lim<T: Float>(
+    spectrogram: &mut Spectrogram<T>,
+    iterations: usize,
+    momentum: T,
+) -> Vec<T> {
+    let window_size = spectrogram.window_size();
+    let slide_size = spectrogram.slide_size();
+    for (i, frame) in spectrogram.frames.iter_mut().enumerate() {
+        for (k, x) in frame.iter_mut().enumerate() {
+            let cycles = (k * i * slide_size % window_size) as f64 / window_size as f64;
+            *x = Complex::from_polar(x.norm(), T::from(TAU * cycles).unwrap());
+        }
+    }
+    reconstruct(spectrogram, iterations, momentum)
+}
+
+/// Same as `fast_griffin_lim()` but starts from the phases of `spectrogram`,
+/// e.g. of the original signal before editing the magnitudes.
+pub fn reconstruct<T: Float>(
+    spectrogram: &mut Spectrogram<T>,
+    iterations: usize,
+    momentum: T,
+) -> Vec<T> {
+    if spectrogram.frames.is_empty() {
+        return vec![];
+    }
+    let slide_size = spectrogram.slide_size();
+    let mut stft = Stft::new(spectrogram.window(), slide_size);
+    let magnitudes = spectrogram.magnitudes();
+    let weight = stft.weight(spectrogram.frames.len());
+    let mut signal = vec![T::zero(); weight.len()];
+    let mut projection = vec![Complex::zero(); spectrogram.bins()];
+    // The last projection onto the magnitudes, while `spectrogram` is extrapolated from it.
+    let mut previous = spectrogram.frames.clone();
+
+    for _ in 0..iterations {
+        stft.overlap_add(&spectrogram.frames, &weight, &mut signal);
+        for (i, (frame, previous)) in spectrogram.frames.iter_mut().zip(&mut previous).enumerate() {
+            stft.analyze(&signal[i * slide_size..], &mut projection);
+            for (((x, p), &c), &m) in frame
+                .iter_mut()
+                .zip(previous.iter_mut())
+                .zip(&projection)
+                .zip(&magnitudes[i])
+            {
+                let c = with_magnitude(c, m);
+                *x = c + (c - *p) * momentum;
+                *p = c;
+            }
+        }
+    }
+
+    spectrogram.frames = previous;
+    istft(spectrogram)
+}
+
+/// Real-time iterative spectrogram inversion with look-ahead (RTISI-LA).
+///
+/// Frames are given one by one, and each is iterated with the `look_ahead` frames after it
+/// before its samples are output, so the output of a frame comes `look_ahead` frames later.
+///
+/// The frames can be of `stft::stft()`, whose padding of `window_size - slide_size` zeros comes out first.
+///
+/// # Example
+/// ```
+/// # use voiche::{phase_reconstruction::Rtisi, stft::stft, windows::hann_window};
+/// let window = hann_window(1024);
+/// let input = vec![0.0f32; 4800];
+///
+/// let mut rtisi = Rtisi::new(window.clone(), 256, 3, 8);
+/// let mut output = vec![];
+/// for magnitude in stft(&window, 256, &input).magnitudes() {
+///     rtisi.process(&magnitude, &mut output);
+/// }
+/// rtisi.finish(&mut output);
+/// output.drain(..1024 - 256);
+/// output.truncate(input.len());
+/// ```
+pub struct Rtisi<T: Float> {
+    /// Number of iterations over the frames in the look-ahead for each new frame.
+    pub iterations: usize,
+    look_ahead: usize,
+    stft: Stft<T>,
+    /// The frames not yet output, oldest first, the first `active` of which are in use.
+    magnitudes: Vec<Vec<T>>,
+    spectra: Vec<Vec<Complex<T>>>,
+    /// The windowed inverse transform of each frame as added to `sum`.
+    contributions: Vec<Vec<T>>,
+    active: usize,
+    /// Overlap-added signal and squared windows from the start of the oldest frame.
+    sum: Vec<T>,
+    weight: Vec<T>,
+    projection: Vec<Complex<T>>,
+    frame: Vec<T>,
+}
+
+impl<T: Float> Rtisi<T> {
+    pub fn new(window: Vec<T>, slide_size: usize, look_ahead: usize, iterations: usize) -> Self {
+        let stft = Stft::new(&window, slide_size);
+        let window_size = window.len();
+        let half_len = stft.fft.half_len();
+        let frames = look_ahead + 1;
+        let len = window_size + look_ahead * slide_size;
+        Rtisi {
+            iterations,
+            look_ahead,
+            magnitudes: vec![vec![T::zero(); half_len]; frames],
+            spectra: vec![vec![Complex::zero(); half_len]; frames],
+            contributions: vec![vec![T::zero(); window_size]; frames],
+            active: 0,
+            sum: vec![T::zero(); len],
+            weight: vec![T::zero(); len],
+            projection: vec![Complex::zero(); half_len],
+            frame: vec![T::zero(); window_size],
+            stft,
+        }
+    }
+
+    pub fn look_ahead(&self) -> usize {
+        self.look_ahead
+    }
+
+    pub fn reset(&mut self) {
+        self.active = 0;
+        self.sum.fill(T::zero());
+        self.weight.fill(T::zero());
+    }
+
+    /// Take a frame of `window.len() / 2 + 1` magnitudes,
+    /// and append `slide_size` samples to `output` once the look-ahead is filled.
+    pub fn process(&mut self, magnitude: &[T], output: &mut Vec<T>) {
+        assert_eq!(magnitude.len(), self.stft.fft.half_len());
+        let slide_size = self.stft.slide_size;
+        let a = self.active;
+        self.active += 1;
+
+        for (w, &x) in self.weight[a * slide_size..]
+            .iter_mut()
+            .zip(&self.stft.window)
+        {
+            *w = *w + x * x;
+        }
+        self.magnitudes[a].copy_from_slice(magnitude);
+        self.contributions[a].fill(T::zero());
+        // The initial phases are from the overlap of the previous frames.
+        self.update(a);
+        for _ in 0..self.iterations {
+            for b in 0..self.active {
+                self.update(b);
+            }
+        }
+
+        if self.look_ahead < self.active {
+            self.commit(output);
+        }
+    }
+
+    /// Output the rest of the frames and their overlap, and reset.
+    pub fn finish(&mut self, output: &mut Vec<T>) {
+        if self.active == 0 {
+            return;
+        }
+        while 0 < self.active {
+            self.commit(output);
+        }
+        let overlap_size = self.stft.window.len() - self.stft.slide_size;
+        output.extend((0..overlap_size).map(|i| normalize(self.sum[i], self.weight[i])));
+        self.reset();
+    }
+
+    /// Project the current reconstruction of the `a`th frame onto its magnitudes.
+    fn update(&mut self, a: usize) {
+        let offset = a * self.stft.slide_size;
+        let window_size = self.stft.window.len();
+        for ((f, &s), &w) in self
+            .frame
+            .iter_mut()
+            .zip(&self.sum[offset..offset + window_size])
+            .zip(&self.weight[offset..])
+        {
+            *f = normalize(s, w);
+        }
+        self.stft.analyze(&self.frame, &mut self.projection);
+        for ((x, &c), &m) in self.spectra[a]
+            .iter_mut()
+            .zip(&self.projection)
+            .zip(&self.magnitudes[a])
+        {
+            *x = with_magnitude(c, m);
+        }
+
+        let synthesized = self.stft.synthesize(&self.spectra[a]);
+        for ((s, c), &y) in self.sum[offset..]
+            .iter_mut()
+            .zip(self.contributions[a].iter_mut())
+            .zip(synthesized)
+        {
+            *s = *s - *c + y;
+            *c = y;
+        }
+    }
+
+    fn commit(&mut self, output: &mut Vec<T>) {
+        let slide_size = self.stft.slide_size;
+        output.extend((0..slide_size).map(|i| normalize(self.sum[i], self.weight[i])));
+        for buf in [&mut self.sum, &mut self.weight] {
+            buf.copy_within(slide_size.., 0);
+            let len = buf.len();
+            buf[len - slide_size..].fill(T::zero());
+        }
+        self.magnitudes.rotate_left(1);
+        self.spectra.rotate_left(1);
+        self.contributions.rotate_left(1);
+        self.active -= 1;
+    }
+}
+
+/// `x` with its phase and the magnitude `m`.
+fn with_magnitude<T: Float>(x: Complex<T>, m: T) -> Complex<T> {
+    let norm = x.norm();
+    if norm == T::zero() {
+        Complex::from(m)
+    } else {
+        x * (m / norm)
+    }
+}
+
+#[test]
+fn test() {
+    use crate::stft::stft;
+
+    let window = crate::windows::hann_window::<f64>(512);
+    let slide_size = 128;
+    // Two gliding tones.
+    let buf: Vec<f64> = (0..8192)
+        .map(|i| {
+            let t = i as f64 / 8192.0;
+            (TAU * (20.0 + 10.0 * t) * t * 100.0).sin() + 0.5 * (TAU * 1500.0 * t).sin()
+        })
+        .collect();
+    let original = stft(&window, slide_size, &buf);
+    let target = original.magnitudes();
+    // Spectral convergence: the relative error of the magnitudes of the reconstruction.
+    let error = |signal: &[f64]| {
+        assert_eq!(signal.len(), buf.len());
+        let (mut diff, mut norm) = (0.0, 0.0);
+        for (a, b) in stft(&window, slide_size, signal)
+            .magnitudes()
+            .iter()
+            .zip(&target)
+        {
+            for (x, y) in a.iter().zip(b) {
+                diff += (x - y) * (x - y);
+                norm += y * y;
+            }
+        }
+        (diff / norm).sqrt()
+    };
+
+    // A consistent spectrogram is reconstructed as is.
+    let signal = reconstruct(&mut original.clone(), 10, 0.99);
+    assert!(signal.iter().zip(&buf).all(|(a, b)| (a - b).abs() < 1e-9));
+
+    let initial = error(&griffin_lim(&mut original.clone(), 0));
+    let gl = error(&griffin_lim(&mut original.clone(), 30));
+    let fgla = error(&fast_griffin_lim(&mut original.clone(), 30, 0.99));
+    assert!(gl < initial / 2.0);
+    assert!(fgla < gl);
+
+    let mut rtisi = Rtisi::new(window.clone(), slide_size, 3, 4);
+    let mut output = vec![];
+    for (i, magnitude) in target.iter().enumerate() {
+        rtisi.process(magnitude, &mut output);
+        assert_eq!(output.len(), (i + 1).saturating_sub(3) * slide_size);
+    }
+    rtisi.finish(&mut output);
+    output.drain(..window.len() - slide_size);
+    output.truncate(buf.len());
+    assert!(error(&output) < initial / 2.0);
+}
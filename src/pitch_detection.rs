@@ -1,7 +1,32 @@
-use rustfft::num_complex::Complex;
+use std::cmp::Ordering;
+
+use rustfft::{num_complex::Complex, num_traits::Zero};
 
 use crate::{apply_window, fft::Fft, Float};
 
+/// A method to detect the pitch of a window.
+pub trait PitchDetector<T: Float> {
+    /// Returns the wavelength in samples and the confidence from 0 to 1,
+    /// or `None` if no pitch is found.
+    fn detect(&mut self, buf: &[T]) -> Option<(T, T)>;
+
+    /// Detect the pitch of successive windows.
+    /// Detectors which smooth over frames override this.
+    fn detect_frames(&mut self, frames: &[&[T]]) -> Vec<Option<(T, T)>> {
+        frames.iter().map(|frame| self.detect(frame)).collect()
+    }
+}
+
+impl<T: Float, D: PitchDetector<T> + ?Sized> PitchDetector<T> for Box<D> {
+    fn detect(&mut self, buf: &[T]) -> Option<(T, T)> {
+        (**self).detect(buf)
+    }
+
+    fn detect_frames(&mut self, frames: &[&[T]]) -> Vec<Option<(T, T)>> {
+        (**self).detect_frames(frames)
+    }
+}
+
 /// Detect pitch from a buffer.
 /// Returns a tuple of wavelength and gain.
 pub fn pitch_detect<T: Float>(
@@ -11,55 +36,574 @@ pub fn pitch_detect<T: Float>(
     min_wavelength: T,
     peak_threshold: T,
 ) -> Option<(T, T)> {
-    let buf: Vec<_> = apply_window(window, buf.iter().copied()).collect();
-    let nsdf = compute_nsdf(&fft, &buf);
-    let mut peaks = compute_peaks(&nsdf[..nsdf.len() / 2]);
+    mcleod(
+        fft,
+        window,
+        buf,
+        min_wavelength,
+        peak_threshold,
+        T::from(0.9).unwrap(),
+        &mut McleodBuffers::new(fft),
+    )
+}
+
+/// Buffers of `mcleod()`, allocated once by `Mpm`.
+#[derive(Clone)]
+struct McleodBuffers<T> {
+    windowed: Vec<T>,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    nsdf: Vec<T>,
+    peaks: Vec<(T, T)>,
+}
+
+impl<T: Float> McleodBuffers<T> {
+    fn new(fft: &Fft<T>) -> Self {
+        let len = fft.len();
+        McleodBuffers {
+            windowed: vec![T::zero(); len],
+            buffer: vec![T::zero(); len],
+            half_spectrum: vec![Complex::zero(); fft.half_len()],
+            nsdf: vec![T::zero(); len],
+            // A peak takes at least two lags of the first half of the NSDF.
+            peaks: Vec::with_capacity(len / 4 + 1),
+        }
+    }
+}
+
+fn mcleod<T: Float>(
+    fft: &Fft<T>,
+    window: &[T],
+    buf: &[T],
+    min_wavelength: T,
+    peak_threshold: T,
+    key_threshold: T,
+    buffers: &mut McleodBuffers<T>,
+) -> Option<(T, T)> {
+    let McleodBuffers {
+        windowed,
+        buffer,
+        half_spectrum,
+        nsdf,
+        peaks,
+    } = buffers;
+    for (w, x) in windowed
+        .iter_mut()
+        .zip(apply_window(window, buf.iter().copied()))
+    {
+        *w = x;
+    }
+    compute_nsdf_into(fft, windowed, buffer, half_spectrum, nsdf);
+    compute_peaks_into(&nsdf[..nsdf.len() / 2], peaks);
     peaks.retain(|p| min_wavelength < p.0);
     let max_peak = peaks.iter().fold(T::zero(), |a, p| a.max(p.1));
     if peak_threshold < max_peak {
         peaks
             .iter()
-            .find(|p| max_peak * T::from(0.9).unwrap() <= p.1)
+            .find(|p| max_peak * key_threshold <= p.1)
             .cloned()
     } else {
         None
     }
 }
 
+/// McLeod pitch method, the same as `pitch_detect()`.
+///
+/// The buffers are allocated once in `new()`, so it can run on an audio thread.
+#[derive(Clone)]
+pub struct Mpm<T: Float> {
+    fft: Fft<T>,
+    window: Vec<T>,
+    buffers: McleodBuffers<T>,
+    pub min_wavelength: T,
+    /// Windows whose highest NSDF peak is below this are unvoiced.
+    pub peak_threshold: T,
+    /// The first NSDF peak above `key_threshold` times the highest one is taken.
+    pub key_threshold: T,
+}
+
+impl<T: Float> Mpm<T> {
+    pub fn new(window: Vec<T>, min_wavelength: T, peak_threshold: T) -> Self {
+        let fft = Fft::new(window.len());
+        Mpm {
+            buffers: McleodBuffers::new(&fft),
+            fft,
+            window,
+            min_wavelength,
+            peak_threshold,
+            key_threshold: T::from(0.9).unwrap(),
+        }
+    }
+}
+
+impl<T: Float> PitchDetector<T> for Mpm<T> {
+    fn detect(&mut self, buf: &[T]) -> Option<(T, T)> {
+        mcleod(
+            &self.fft,
+            &self.window,
+            buf,
+            self.min_wavelength,
+            self.peak_threshold,
+            self.key_threshold,
+            &mut self.buffers,
+        )
+    }
+}
+
+/// YIN (de Cheveigné & Kawahara).
+///
+/// Wavelengths up to half of the window size can be detected.
+#[derive(Clone)]
+pub struct Yin<T: Float> {
+    fft: Fft<T>,
+    pub min_wavelength: T,
+    pub max_wavelength: T,
+    /// The first dip of the CMNDF below this is taken.
+    pub threshold: T,
+}
+
+impl<T: Float> Yin<T> {
+    pub fn new(window_size: usize, min_wavelength: T, max_wavelength: T) -> Self {
+        Yin {
+            fft: Fft::new(window_size),
+            min_wavelength,
+            max_wavelength,
+            threshold: T::from(0.15).unwrap(),
+        }
+    }
+}
+
+impl<T: Float> PitchDetector<T> for Yin<T> {
+    fn detect(&mut self, buf: &[T]) -> Option<(T, T)> {
+        let cmndf = compute_cmndf(&self.fft, buf);
+        let lags = lag_range(&cmndf, self.min_wavelength, self.max_wavelength);
+        let lag = first_dip(&cmndf, lags, self.threshold)?;
+        Some((
+            T::from(lag).unwrap() + parabolic_vertex(&cmndf, lag),
+            T::one() - cmndf[lag].min(T::one()),
+        ))
+    }
+}
+
+/// Probabilistic YIN (Mauch & Dixon).
+///
+/// The YIN threshold is spread over a beta distribution,
+/// so each window gives several candidates with probabilities.
+/// `detect_frames()` chooses a path through the candidates by the Viterbi algorithm
+/// over states of log-spaced wavelength bins, voiced or unvoiced.
+#[derive(Clone)]
+pub struct Pyin<T: Float> {
+    fft: Fft<T>,
+    pub min_wavelength: T,
+    pub max_wavelength: T,
+    /// Probability of switching between voiced and unvoiced per frame.
+    pub voicing_switch: T,
+    /// Largest change of the pitch per frame in bins (a bin is 20 cents).
+    pub max_jump: usize,
+    thresholds: Vec<(T, T)>,
+}
+
+const PYIN_BINS_PER_OCTAVE: f64 = 60.0;
+
+impl<T: Float> Pyin<T> {
+    pub fn new(window_size: usize, min_wavelength: T, max_wavelength: T) -> Self {
+        // Beta(2, 18) distribution over thresholds 0.01, 0.02, ..., 1.
+        let beta = |x: f64| x * (1.0 - x).powi(17);
+        let sum: f64 = (1..=100).map(|i| beta(i as f64 / 100.0)).sum();
+        let thresholds = (1..=100)
+            .map(|i| {
+                let x = i as f64 / 100.0;
+                (T::from(x).unwrap(), T::from(beta(x) / sum).unwrap())
+            })
+            .collect();
+        Pyin {
+            fft: Fft::new(window_size),
+            min_wavelength,
+            max_wavelength,
+            voicing_switch: T::from(0.01).unwrap(),
+            max_jump: 12,
+            thresholds,
+        }
+    }
+
+    /// Wavelength candidates and their probabilities.
+    /// The sum of the probabilities is the voicing probability.
+    pub fn candidates(&self, buf: &[T]) -> Vec<(T, T)> {
+        let cmndf = compute_cmndf(&self.fft, buf);
+        let lags = lag_range(&cmndf, self.min_wavelength, self.max_wavelength);
+        if lags.is_empty() {
+            return vec![];
+        }
+        let global_min = lags
+            .clone()
+            .min_by(|&a, &b| cmndf[a].partial_cmp(&cmndf[b]).unwrap_or(Ordering::Equal))
+            .unwrap();
+
+        let mut candidates: Vec<(usize, T)> = Vec::new();
+        for &(threshold, probability) in &self.thresholds {
+            let (lag, probability) = match first_dip(&cmndf, lags.clone(), threshold) {
+                Some(lag) => (lag, probability),
+                // Rarely, the global minimum is voiced.
+                None => (global_min, probability * T::from(0.01).unwrap()),
+            };
+            match candidates.iter_mut().find(|c| c.0 == lag) {
+                Some(c) => c.1 = c.1 + probability,
+                None => candidates.push((lag, probability)),
+            }
+        }
+        candidates
+            .into_iter()
+            .map(|(lag, p)| (T::from(lag).unwrap() + parabolic_vertex(&cmndf, lag), p))
+            .collect()
+    }
+
+    fn bin_count(&self) -> usize {
+        self.bin(self.max_wavelength) + 1
+    }
+
+    fn bin(&self, wavelength: T) -> usize {
+        let octaves = (wavelength / self.min_wavelength).log2().max(T::zero());
+        (octaves * T::from(PYIN_BINS_PER_OCTAVE).unwrap())
+            .round()
+            .to_usize()
+            .unwrap()
+    }
+
+    fn bin_wavelength(&self, bin: usize) -> T {
+        self.min_wavelength * T::from(2f64.powf(bin as f64 / PYIN_BINS_PER_OCTAVE)).unwrap()
+    }
+}
+
+impl<T: Float> PitchDetector<T> for Pyin<T> {
+    /// The most probable candidate, without smoothing.
+    fn detect(&mut self, buf: &[T]) -> Option<(T, T)> {
+        let candidates = self.candidates(buf);
+        let voiced = candidates.iter().fold(T::zero(), |a, c| a + c.1);
+        let best = candidates
+            .iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+        (T::from(0.5).unwrap() < voiced).then_some((best.0, voiced))
+    }
+
+    fn detect_frames(&mut self, frames: &[&[T]]) -> Vec<Option<(T, T)>> {
+        let bins = self.bin_count();
+        // States are `bin` for voiced and `bins + bin` for unvoiced.
+        let states = bins * 2;
+        let stay = T::one() - self.voicing_switch;
+
+        // Triangular weights of pitch transitions.
+        let jump = self.max_jump as isize;
+        let weights: Vec<T> = (-jump..=jump)
+            .map(|d| T::from(jump + 1 - d.abs()).unwrap())
+            .collect();
+        let weight_sum = weights.iter().fold(T::zero(), |a, &w| a + w);
+
+        let mut all_candidates = Vec::with_capacity(frames.len());
+        let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(frames.len());
+        let mut probabilities = vec![T::one() / T::from(states).unwrap(); states];
+        let mut observations = vec![T::zero(); states];
+        let mut next = vec![T::zero(); states];
+
+        for (f, frame) in frames.iter().enumerate() {
+            let candidates = self.candidates(frame);
+            let voiced = candidates.iter().fold(T::zero(), |a, c| a + c.1);
+            observations[..bins].fill(T::zero());
+            for &(wavelength, p) in &candidates {
+                let bin = self.bin(wavelength).min(bins - 1);
+                observations[bin] = observations[bin] + p;
+            }
+            observations[bins..].fill((T::one() - voiced).max(T::zero()) / T::from(bins).unwrap());
+
+            let mut pointers = vec![0; states];
+            for (state, (n, pointer)) in next.iter_mut().zip(&mut pointers).enumerate() {
+                let (bin, is_voiced) = (state % bins, state < bins);
+                let mut best = (T::zero(), state);
+                if 0 < f {
+                    for (prev_bin, &w) in (bin as isize - jump..=bin as isize + jump).zip(&weights)
+                    {
+                        if prev_bin < 0 || bins as isize <= prev_bin {
+                            continue;
+                        }
+                        for prev_voiced in [true, false] {
+                            let prev = prev_bin as usize + if prev_voiced { 0 } else { bins };
+                            let switch = if prev_voiced == is_voiced {
+                                stay
+                            } else {
+                                self.voicing_switch
+                            };
+                            let p = probabilities[prev] * w / weight_sum * switch;
+                            if best.0 < p {
+                                best = (p, prev);
+                            }
+                        }
+                    }
+                } else {
+                    best.0 = probabilities[state];
+                }
+                *n = best.0 * observations[state];
+                *pointer = best.1;
+            }
+
+            // Normalize not to underflow.
+            let sum = next.iter().fold(T::zero(), |a, &x| a + x);
+            if T::zero() < sum {
+                for (p, &n) in probabilities.iter_mut().zip(&next) {
+                    *p = n / sum;
+                }
+            } else {
+                probabilities.fill(T::one() / T::from(states).unwrap());
+            }
+            backpointers.push(pointers);
+            all_candidates.push((candidates, voiced));
+        }
+
+        let mut state = (0..states)
+            .max_by(|&a, &b| {
+                probabilities[a]
+                    .partial_cmp(&probabilities[b])
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap_or(0);
+        let mut path = vec![0; frames.len()];
+        for f in (0..frames.len()).rev() {
+            path[f] = state;
+            state = backpointers[f][state];
+        }
+
+        path.into_iter()
+            .zip(all_candidates)
+            .map(|(state, (candidates, voiced))| {
+                if bins <= state {
+                    return None;
+                }
+                // The most probable candidate in the bin, or the center of the bin.
+                let wavelength = candidates
+                    .iter()
+                    .filter(|c| self.bin(c.0).min(bins - 1) == state)
+                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                    .map_or_else(|| self.bin_wavelength(state), |c| c.0);
+                Some((wavelength, voiced.min(T::one())))
+            })
+            .collect()
+    }
+}
+
+/// Cumulative mean normalized difference function (CMNDF) of YIN,
+/// for lags up to `buf.len() / 2`.
+pub fn compute_cmndf<T: Float>(fft: &Fft<T>, buf: &[T]) -> Vec<T> {
+    let len = buf.len();
+    if len == 0 {
+        return vec![];
+    }
+    let size = len / 2;
+
+    // r(lag) = sum of buf[j] * buf[j + lag] for j < size
+    let mut head = vec![T::zero(); len];
+    head[..size].copy_from_slice(&buf[..size]);
+    let mut spectrum_head = vec![Complex::zero(); fft.half_len()];
+    fft.forward_real(&mut head, &mut spectrum_head);
+    let mut spectrum = vec![Complex::zero(); fft.half_len()];
+    fft.forward_real(&mut buf.to_vec(), &mut spectrum);
+    for (x, y) in spectrum.iter_mut().zip(&spectrum_head) {
+        *x = *x * y.conj();
+    }
+    let mut acf = vec![T::zero(); len];
+    fft.inverse_real(&mut spectrum, &mut acf);
+    let scale = T::one() / T::from(len).unwrap();
+
+    let mut energy = buf[..size].iter().fold(T::zero(), |a, &x| a + x * x);
+    let first_energy = energy;
+    let mut cmndf = vec![T::one(); size];
+    let mut sum = T::zero();
+    for lag in 1..size {
+        energy = energy - buf[lag - 1].powi(2) + buf[lag + size - 1].powi(2);
+        let difference =
+            (first_energy + energy - T::from(2).unwrap() * acf[lag] * scale).max(T::zero());
+        sum = sum + difference;
+        cmndf[lag] = if T::zero() < sum {
+            difference * T::from(lag).unwrap() / sum
+        } else {
+            T::one()
+        };
+    }
+    cmndf
+}
+
+fn lag_range<T: Float>(
+    cmndf: &[T],
+    min_wavelength: T,
+    max_wavelength: T,
+) -> std::ops::Range<usize> {
+    let start = min_wavelength.ceil().to_usize().unwrap().max(1);
+    // Empty for an empty CMNDF, e.g. of an empty buffer.
+    let end = (max_wavelength.floor().to_usize().unwrap() + 1).min(cmndf.len().saturating_sub(1));
+    start..end.max(start)
+}
+
+/// The local minimum after the CMNDF first goes below `threshold`.
+fn first_dip<T: Float>(
+    cmndf: &[T],
+    mut lags: std::ops::Range<usize>,
+    threshold: T,
+) -> Option<usize> {
+    let mut lag = lags.find(|&lag| cmndf[lag] < threshold)?;
+    while lag + 1 < cmndf.len() && cmndf[lag + 1] < cmndf[lag] {
+        lag += 1;
+    }
+    Some(lag)
+}
+
+/// Offset of the vertex of the parabola through `y[i - 1]`, `y[i]` and `y[i + 1]`.
+fn parabolic_vertex<T: Float>(y: &[T], i: usize) -> T {
+    if i == 0 || y.len() <= i + 1 {
+        return T::zero();
+    }
+    let t = y[i - 1] - T::from(2).unwrap() * y[i] + y[i + 1];
+    if t == T::zero() {
+        return T::zero();
+    }
+    (y[i - 1] - y[i + 1]) / (T::from(2).unwrap() * t)
+}
+
+/// A point placed once per period, used by `sola::psola()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchMark<T> {
+    pub position: usize,
+    /// The period around this mark in samples.
+    pub wavelength: T,
+    pub voiced: bool,
+}
+
+/// Place pitch marks on the peaks of each period of `buf`.
+///
+/// The wavelength is detected by `pitch_detect()` every `slide_size` samples.
+/// Where no pitch is detected, the marks are placed every `window.len() / 8` samples
+/// and are marked as unvoiced.
+pub fn pitch_marks<T: Float>(
+    fft: &Fft<T>,
+    window: &[T],
+    buf: &[T],
+    slide_size: usize,
+    min_wavelength: T,
+    peak_threshold: T,
+) -> Vec<PitchMark<T>> {
+    let window_size = window.len();
+    let detections: Vec<_> = buf
+        .windows(window_size)
+        .step_by(slide_size)
+        .map(|b| pitch_detect(fft, window, b, min_wavelength, peak_threshold).map(|p| p.0))
+        .collect();
+    let unvoiced_wavelength = T::from(window_size / 8).unwrap();
+
+    let mut marks = Vec::new();
+    let mut expected = 0;
+    while expected < buf.len() {
+        let frame = (expected.saturating_sub(window_size / 2) + slide_size / 2) / slide_size;
+        let detection = detections
+            .get(frame.min(detections.len().saturating_sub(1)))
+            .copied()
+            .flatten();
+
+        let mark = if let Some(wavelength) = detection {
+            // Snap to the highest sample near the expected position.
+            let reach = (wavelength / T::from(4).unwrap()).to_usize().unwrap();
+            let start = marks
+                .last()
+                .map_or(0, |m: &PitchMark<T>| {
+                    m.position + (wavelength / T::from(2).unwrap()).to_usize().unwrap()
+                })
+                .max(expected.saturating_sub(reach));
+            let end = (expected + reach + 1).min(buf.len()).max(start + 1);
+            let position = (start..end)
+                .filter(|&i| i < buf.len())
+                .max_by(|&a, &b| buf[a].partial_cmp(&buf[b]).unwrap_or(Ordering::Equal))
+                .unwrap_or(expected);
+            PitchMark {
+                position,
+                wavelength,
+                voiced: true,
+            }
+        } else {
+            PitchMark {
+                position: expected,
+                wavelength: unvoiced_wavelength,
+                voiced: false,
+            }
+        };
+        expected = mark.position + mark.wavelength.round().to_usize().unwrap().max(1);
+        marks.push(mark);
+    }
+    marks
+}
+
 /// Normalized Square Difference Function (NSDF)
 pub fn compute_nsdf<T: Float>(fft: &Fft<T>, buf: &[T]) -> Vec<T> {
-    let mut cmps: Vec<_> = buf.iter().copied().map(Complex::from).collect();
-    fft.forward(&mut cmps);
-    compute_nsdf_from_spectrum(fft, buf, cmps)
+    let mut buffer = vec![T::zero(); buf.len()];
+    let mut half_spectrum = vec![Complex::zero(); fft.half_len()];
+    let mut nsdf = vec![T::zero(); buf.len()];
+    compute_nsdf_into(fft, buf, &mut buffer, &mut half_spectrum, &mut nsdf);
+    nsdf
 }
 
-pub fn compute_nsdf_from_spectrum<T: Float>(
+/// `compute_nsdf()` into `nsdf`, with `buffer` and `half_spectrum` as the work space.
+fn compute_nsdf_into<T: Float>(
     fft: &Fft<T>,
     buf: &[T],
-    mut spectrum: Vec<Complex<T>>,
+    buffer: &mut [T],
+    half_spectrum: &mut [Complex<T>],
+    nsdf: &mut [T],
+) {
+    buffer.copy_from_slice(buf);
+    fft.forward_real(buffer, half_spectrum);
+    nsdf_from_half_spectrum(fft, buf, half_spectrum, buffer, nsdf);
+}
+
+/// Same as `compute_nsdf()` but takes the `len / 2 + 1` bins of the spectrum of `buf`,
+/// as given by `Fft::forward_real()`.
+pub fn compute_nsdf_from_half_spectrum<T: Float>(
+    fft: &Fft<T>,
+    buf: &[T],
+    mut half_spectrum: Vec<Complex<T>>,
 ) -> Vec<T> {
-    for x in &mut spectrum {
+    let mut acf = vec![T::zero(); buf.len()];
+    let mut nsdf = vec![T::zero(); buf.len()];
+    nsdf_from_half_spectrum(fft, buf, &mut half_spectrum, &mut acf, &mut nsdf);
+    nsdf
+}
+
+fn nsdf_from_half_spectrum<T: Float>(
+    fft: &Fft<T>,
+    buf: &[T],
+    half_spectrum: &mut [Complex<T>],
+    acf: &mut [T],
+    nsdf: &mut [T],
+) {
+    for x in half_spectrum.iter_mut() {
         *x = Complex::from(x.norm_sqr());
     }
-    fft.inverse(&mut spectrum);
+    fft.inverse_real(half_spectrum, acf);
 
     let len = buf.len();
-    let mut nsdf = vec![T::zero(); len];
     let mut m = T::epsilon();
     for i in 0..len {
         let inv = len - i - 1;
         m = m + buf[i].powi(2) + buf[inv].powi(2);
-        nsdf[inv] = T::from(2.0).unwrap() * spectrum[inv].re / (m * T::from(len).unwrap());
+        nsdf[inv] = T::from(2.0).unwrap() * acf[inv] / (m * T::from(len).unwrap());
     }
-
-    nsdf
 }
 
 pub fn compute_peaks<T: Float>(nsdf: &[T]) -> Vec<(T, T)> {
+    let mut peaks = Vec::with_capacity(32);
+    compute_peaks_into(nsdf, &mut peaks);
+    peaks
+}
+
+/// `compute_peaks()` into `peaks`, which is cleared first.
+fn compute_peaks_into<T: Float>(nsdf: &[T], peaks: &mut Vec<(T, T)>) {
     let zero = T::zero();
     let mut peak = (zero, zero);
-    let mut peaks = Vec::with_capacity(32);
     let mut is_first = true;
+    peaks.clear();
 
     for i in 0..nsdf.len().saturating_sub(3) {
         if nsdf[i + 1] < T::zero() {
@@ -77,9 +621,67 @@ pub fn compute_peaks<T: Float>(nsdf: &[T]) -> Vec<(T, T)> {
             let d = (nsdf[i] - nsdf[i + 2]) / t;
             let c = nsdf[i + 1] - t * d * d / T::from(4.0).unwrap();
             if peak.1 < c {
-                peak = (T::from(i).unwrap() + d, c);
+                peak = (T::from(i + 1).unwrap() + d, c);
             }
         }
     }
-    peaks
+}
+
+#[test]
+fn test_detectors() {
+    use std::f64::consts::TAU;
+
+    let window_size = 1024;
+    let sample_rate = 16000.0;
+    let f0 = 150.0;
+    // A breathy voice: a weak fundamental and noise.
+    let noise = crate::test_util::noise(8000, 1);
+    let buf: Vec<f64> = (0..8000)
+        .map(|i| {
+            let t = i as f64 / sample_rate;
+            let voice = (1..=6)
+                .map(|k| {
+                    (TAU * f0 * k as f64 * t).sin() * if k == 1 { 0.2 } else { 1.0 / k as f64 }
+                })
+                .sum::<f64>();
+            // Silent in the middle.
+            let gate = if (3000..5000).contains(&i) { 0.0 } else { 1.0 };
+            voice * gate + noise[i] * 0.1
+        })
+        .collect();
+    let frames: Vec<&[f64]> = buf.windows(window_size).step_by(256).collect();
+    let wavelength = sample_rate / f0;
+
+    let mut detectors: Vec<Box<dyn PitchDetector<f64>>> = vec![
+        Box::new(Mpm::new(
+            crate::windows::hann_window(window_size),
+            20.0,
+            0.5,
+        )),
+        Box::new(Yin::new(window_size, 20.0, 400.0)),
+        Box::new(Pyin::new(window_size, 20.0, 400.0)),
+    ];
+    for detector in &mut detectors {
+        let detections = detector.detect_frames(&frames);
+        assert_eq!(detections.len(), frames.len());
+        // The frames inside the voiced parts.
+        for &i in &[0, 4, 20, 25] {
+            let (w, confidence) = detections[i].unwrap();
+            assert!((w - wavelength).abs() < 1.0, "{} {}", i, w);
+            assert!(0.0 < confidence && confidence <= 1.0);
+        }
+    }
+
+    // No pitch in an empty buffer.
+    assert_eq!(Yin::new(window_size, 20.0, 400.0).detect(&[]), None);
+    assert_eq!(Pyin::new(window_size, 20.0, 400.0).detect(&[]), None);
+
+    // pYIN tells the silence from the voice.
+    let detections = Pyin::new(window_size, 20.0, 400.0).detect_frames(&frames);
+    assert!(detections[12..16].iter().all(|d| d.is_none()));
+    assert!(detections
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(8..20).contains(i))
+        .all(|(_, d)| d.is_some()));
 }
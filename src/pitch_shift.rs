@@ -1,72 +1,450 @@
-use std::f64::consts::TAU;
+use std::{cmp::Ordering, f64::consts::TAU};
 
 use rustfft::{
     num_complex::Complex,
     num_traits::{Num, One, Zero},
 };
 
-use crate::{fft::fill_right_part_of_spectrum, Float};
+use crate::{fft::fill_right_part_of_spectrum, spectral::SpectralProcessor, Float};
 
 pub fn process_spectrum<T: Float>(
     slide_size: usize,
-    pitch_shift: &mut impl FnMut(&[Complex<T>], T, usize) -> Vec<Complex<T>>,
+    pitch_shifter: &mut PitchShifter<T>,
     pitch: T,
     spectrum: &mut [Complex<T>],
 ) {
-    let mut shifted_spectrum = pitch_shift(spectrum, pitch, slide_size);
+    let mut shifted_spectrum = pitch_shifter.process(spectrum, pitch, slide_size);
 
     remove_aliasing(pitch, &mut shifted_spectrum);
 
     spectrum.copy_from_slice(&shifted_spectrum);
 }
 
-pub fn pitch_shifter<T: Float>(
-    len: usize,
-) -> impl FnMut(&[Complex<T>], T, usize) -> Vec<Complex<T>> {
-    let mut prev_input_phases = vec![T::zero(); len];
-    let mut prev_output_phases = vec![T::zero(); len];
+/// Pitch shifting as a `SpectralProcessor`.
+#[derive(Clone)]
+pub struct PitchShiftProcessor<T: Float> {
+    pub pitch: T,
+    slide_size: usize,
+    pitch_shifter: PitchShifter<T>,
+    shifted_spectrum: Vec<Complex<T>>,
+}
+
+impl<T: Float> PitchShiftProcessor<T> {
+    pub fn new(window_size: usize, slide_size: usize, pitch: T) -> Self {
+        Self::with_options(window_size, slide_size, pitch, PitchShiftOptions::default())
+    }
+
+    pub fn with_options(
+        window_size: usize,
+        slide_size: usize,
+        pitch: T,
+        options: PitchShiftOptions,
+    ) -> Self {
+        PitchShiftProcessor {
+            pitch,
+            slide_size,
+            pitch_shifter: PitchShifter::with_options(window_size, options),
+            shifted_spectrum: vec![Complex::zero(); window_size],
+        }
+    }
+
+    pub fn pitch_shifter_mut(&mut self) -> &mut PitchShifter<T> {
+        &mut self.pitch_shifter
+    }
+}
+
+impl<T: Float> SpectralProcessor<T> for PitchShiftProcessor<T> {
+    /// `spectrum` can also be the `len / 2 + 1` bins of the spectrum.
+    fn process(&mut self, spectrum: &mut [Complex<T>]) {
+        let window_size = self.shifted_spectrum.len();
+        let shifted_spectrum = &mut self.shifted_spectrum[..spectrum.len()];
+        self.pitch_shifter
+            .process_into(spectrum, self.pitch, self.slide_size, shifted_spectrum);
+        if spectrum.len() == window_size {
+            remove_aliasing(self.pitch, shifted_spectrum);
+        } else {
+            remove_aliasing_half(self.pitch, shifted_spectrum);
+        }
+        spectrum.copy_from_slice(shifted_spectrum);
+    }
+
+    fn process_half(&mut self, half_spectrum: &mut [Complex<T>], _: &mut [Complex<T>]) {
+        self.process(half_spectrum);
+    }
+
+    fn reset(&mut self) {
+        self.pitch_shifter.reset();
+    }
+
+    fn frequency_ratio(&self) -> T {
+        self.pitch
+    }
+}
+
+/// How the phases of neighbouring bins are kept consistent by `PitchShifter`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhaseLocking {
+    /// Every bin accumulates its phase on its own.
+    #[default]
+    Off,
+    /// Identity phase locking (Laroche & Dolson).
+    /// Bins around a spectral peak are moved together with the peak
+    /// and keep their analysis phases relative to it.
+    Identity,
+    /// Scaled phase locking.
+    /// Like `Identity`, but the phase of a peak is continued from the peak of the previous frame
+    /// whose region it was in, so moving partials stay coherent.
+    /// The scaling factor is 1 since the time scale is not changed.
+    Scaled,
+}
+
+/// How `PitchShifter` maps the bins of the input onto the shifted bins.
+///
+/// This is used only when `PhaseLocking::Off`,
+/// since phase locking moves the regions around peaks as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinMapping {
+    /// Each bin copies the nearest source bin.
+    /// Cheap, but some source bins are duplicated and others dropped,
+    /// which sounds metallic at small pitch ratios.
+    #[default]
+    Nearest,
+    /// Source bins are spread over the neighbouring bins with a triangular kernel.
+    Linear,
+    /// Source bins are spread over the neighbouring bins with a Lanczos (windowed sinc) kernel.
+    Sinc,
+}
+
+impl BinMapping {
+    fn radius(self) -> f64 {
+        match self {
+            BinMapping::Nearest => 0.5,
+            BinMapping::Linear => 1.0,
+            BinMapping::Sinc => 2.0,
+        }
+    }
+
+    fn weight<T: Float>(self, distance: T) -> T {
+        let d = distance.abs();
+        let radius = T::from(self.radius()).unwrap();
+        if radius <= d {
+            return T::zero();
+        }
+        match self {
+            BinMapping::Nearest => T::one(),
+            BinMapping::Linear => T::one() - d,
+            BinMapping::Sinc => sinc(d) * sinc(d / radius),
+        }
+    }
+
+    /// Norm and frequency of the shifted bin `i`,
+    /// as the weighted average of the source bins around `i / pitch`.
+    /// The kernel is widened when `pitch > 1` so that every bin is covered.
+    fn interpolate<T: Float>(self, analysis: &[[T; 2]], i: usize, pitch: T) -> [T; 2] {
+        let width = pitch.max(T::one());
+        let center = T::from(i).unwrap() / pitch;
+        let reach = T::from(self.radius()).unwrap() * width / pitch;
+        let start = (center - reach).ceil().max(T::zero()).to_usize().unwrap();
+        let end = (center + reach)
+            .floor()
+            .to_usize()
+            .unwrap()
+            .min(analysis.len() - 1);
+
+        let mut weight_sum = T::zero();
+        let mut norm = T::zero();
+        let mut freq_weight_sum = T::zero();
+        let mut freq = T::zero();
+        for (k, &[n, f]) in analysis.iter().enumerate().take(end + 1).skip(start) {
+            let distance = (T::from(i).unwrap() - T::from(k).unwrap() * pitch) / width;
+            let w = self.weight(distance);
+            weight_sum = weight_sum + w;
+            norm = norm + n * w;
+            // The frequency is dominated by the louder bins.
+            let fw = w.max(T::zero()) * n;
+            freq_weight_sum = freq_weight_sum + fw;
+            freq = freq + f * fw;
+        }
+
+        if weight_sum <= T::zero() || freq_weight_sum <= T::zero() {
+            return [T::zero(), T::zero()];
+        }
+        [
+            (norm / weight_sum).max(T::zero()),
+            freq / freq_weight_sum * pitch,
+        ]
+    }
+}
+
+fn sinc<T: Float>(x: T) -> T {
+    if x == T::zero() {
+        T::one()
+    } else {
+        (x * T::PI()).sin() / (x * T::PI())
+    }
+}
+
+/// Options of `PitchShifter`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PitchShiftOptions {
+    pub phase_locking: PhaseLocking,
+    pub bin_mapping: BinMapping,
+    /// Sensitivity of the transient detection, from 0 (disabled) to 1.
+    ///
+    /// A frame is a transient if the spectral flux,
+    /// the increase of the norms from the previous frame relative to the total norm,
+    /// exceeds `1 - transient_sensitivity`.
+    /// On transients the output phases are reset to the analysis phases,
+    /// so onsets such as plosives do not smear. 0.5 is a good start.
+    pub transient_sensitivity: f64,
+}
+
+/// Phase vocoder which shifts the pitch of a spectrum.
+///
+/// The phases of the previous frame are carried over to the next call.
+#[derive(Clone)]
+pub struct PitchShifter<T: Float> {
+    pub options: PitchShiftOptions,
+    prev_input_phases: Vec<T>,
+    prev_output_phases: Vec<T>,
+    analysis: Vec<[T; 2]>,
+    prev_norms: Vec<T>,
+    transient: bool,
+    peaks: Vec<usize>,
+    prev_peaks: Vec<usize>,
+    next_peaks: Vec<usize>,
+    last_output_phases: Vec<T>,
+}
+
+impl<T: Float> PitchShifter<T> {
+    pub fn new(len: usize) -> Self {
+        Self::with_options(len, PitchShiftOptions::default())
+    }
+
+    pub fn with_options(len: usize, options: PitchShiftOptions) -> Self {
+        PitchShifter {
+            options,
+            prev_input_phases: vec![T::zero(); len],
+            prev_output_phases: vec![T::zero(); len],
+            analysis: vec![[T::zero(); 2]; len / 2 + 1],
+            prev_norms: vec![T::zero(); len / 2 + 1],
+            transient: false,
+            peaks: Vec::with_capacity(len / 2 + 1),
+            prev_peaks: (0..len / 2 + 1).collect(),
+            next_peaks: (0..len / 2 + 1).collect(),
+            last_output_phases: vec![T::zero(); len / 2 + 1],
+        }
+    }
+
+    /// Forget the phases of the previous frame.
+    pub fn reset(&mut self) {
+        self.prev_input_phases.fill(T::zero());
+        self.prev_output_phases.fill(T::zero());
+        self.prev_norms.fill(T::zero());
+        self.transient = false;
+        for (i, x) in self.prev_peaks.iter_mut().enumerate() {
+            *x = i;
+        }
+    }
+
+    pub fn prev_input_phases(&self) -> &[T] {
+        &self.prev_input_phases
+    }
+
+    pub fn prev_output_phases(&self) -> &[T] {
+        &self.prev_output_phases
+    }
+
+    /// Whether the last processed frame was detected as a transient.
+    pub fn is_transient(&self) -> bool {
+        self.transient
+    }
+
+    pub fn process(
+        &mut self,
+        spectrum: &[Complex<T>],
+        pitch: T,
+        slide_size: usize,
+    ) -> Vec<Complex<T>> {
+        let mut shifted_spectrum = vec![Complex::zero(); spectrum.len()];
+        self.process_into(spectrum, pitch, slide_size, &mut shifted_spectrum);
+        shifted_spectrum
+    }
 
-    move |spectrum, pitch, slide_size| {
-        let len = spectrum.len();
+    /// Same as `process()` but writes into `shifted_spectrum`.
+    ///
+    /// `spectrum` can also be the `len / 2 + 1` bins of the spectrum of a real signal,
+    /// and then `shifted_spectrum` is of the same length.
+    pub fn process_into(
+        &mut self,
+        spectrum: &[Complex<T>],
+        pitch: T,
+        slide_size: usize,
+        shifted_spectrum: &mut [Complex<T>],
+    ) {
+        let len = self.prev_input_phases.len();
+        let pre = &mut self.analysis;
 
-        let mut pre = vec![[T::zero(); 2]; len / 2 + 1];
         for i in 0..len / 2 + 1 {
             let (norm, phase) = spectrum[i].to_polar();
             let bin_center_freq = T::from(TAU * i as f64 / len as f64).unwrap();
 
             let phase_diff =
-                phase - prev_input_phases[i] - bin_center_freq * T::from(slide_size).unwrap();
+                phase - self.prev_input_phases[i] - bin_center_freq * T::from(slide_size).unwrap();
             let phase_diff = wrap_phase(phase_diff);
-            prev_input_phases[i] = phase;
+            self.prev_input_phases[i] = phase;
             let bin_deviation =
                 phase_diff * T::from(len as f64 / (slide_size as f64 * TAU)).unwrap();
 
             pre[i] = [norm, T::from(i).unwrap() + bin_deviation];
         }
+        self.transient = self.detect_transient();
 
-        let mut shifted_spectrum = spectrum.to_vec();
-        for i in 0..len / 2 + 1 {
-            let shifted_bin = (T::from(i).unwrap() / pitch).round().to_usize().unwrap();
-            let post = if shifted_bin > len / 2 {
-                [T::zero(), T::zero()]
+        let transient = self.transient;
+        let pre = &self.analysis;
+
+        match self.options.phase_locking {
+            PhaseLocking::Off => {
+                for (i, x) in shifted_spectrum.iter_mut().enumerate().take(len / 2 + 1) {
+                    let [norm, freq] = match self.options.bin_mapping {
+                        BinMapping::Nearest => {
+                            let shifted_bin =
+                                (T::from(i).unwrap() / pitch).round().to_usize().unwrap();
+                            if shifted_bin > len / 2 {
+                                [T::zero(), T::zero()]
+                            } else {
+                                [pre[shifted_bin][0], pre[shifted_bin][1] * pitch]
+                            }
+                        }
+                        mapping => mapping.interpolate(pre, i, pitch),
+                    };
+
+                    let phase = if transient {
+                        let source_bin = (T::from(i).unwrap() / pitch).round().to_usize().unwrap();
+                        self.prev_input_phases[source_bin.min(len / 2)]
+                    } else {
+                        wrap_phase(
+                            self.prev_output_phases[i]
+                                + Self::phase_advance(i, freq, len, slide_size),
+                        )
+                    };
+                    *x = Complex::from_polar(norm, phase);
+                    self.prev_output_phases[i] = phase;
+                }
+            }
+            PhaseLocking::Identity => self.shift_peaks(pitch, slide_size, false, shifted_spectrum),
+            PhaseLocking::Scaled => self.shift_peaks(pitch, slide_size, true, shifted_spectrum),
+        }
+
+        if shifted_spectrum.len() == len {
+            fill_right_part_of_spectrum(shifted_spectrum);
+        }
+    }
+
+    /// Spectral flux of the analysis against the previous frame.
+    fn detect_transient(&mut self) -> bool {
+        let mut flux = T::zero();
+        let mut total = T::zero();
+        for (prev_norm, &[norm, _]) in self.prev_norms.iter_mut().zip(&self.analysis) {
+            flux = flux + (norm - *prev_norm).max(T::zero());
+            total = total + norm;
+            *prev_norm = norm;
+        }
+
+        let sensitivity = self.options.transient_sensitivity;
+        0.0 < sensitivity && T::zero() < total && T::from(1.0 - sensitivity).unwrap() < flux / total
+    }
+
+    fn phase_advance(i: usize, freq: T, len: usize, slide_size: usize) -> T {
+        let bin_deviation = freq - T::from(i).unwrap();
+        let phase_diff = bin_deviation * T::from(TAU * slide_size as f64 / len as f64).unwrap();
+        let bin_center_freq = T::from(TAU * i as f64 / len as f64).unwrap();
+        phase_diff + bin_center_freq * T::from(slide_size).unwrap()
+    }
+
+    /// Move the region around each spectral peak to its shifted frequency as a whole,
+    /// rotating the phases of the region by the same amount as its peak.
+    ///
+    /// If `track` is set, a peak continues the phase of the peak
+    /// whose region covered its bin in the previous frame.
+    fn shift_peaks(
+        &mut self,
+        pitch: T,
+        slide_size: usize,
+        track: bool,
+        shifted_spectrum: &mut [Complex<T>],
+    ) {
+        let len = self.prev_input_phases.len();
+        let half = len / 2 + 1;
+        let norm = |i: usize| self.analysis[i][0];
+
+        self.peaks.clear();
+        for i in 0..half {
+            let left = i == 0 || norm(i - 1) < norm(i);
+            let right = i + 1 == half || norm(i + 1) <= norm(i);
+            if T::zero() < norm(i) && left && right {
+                self.peaks.push(i);
+            }
+        }
+
+        shifted_spectrum[..half].fill(Complex::zero());
+        self.last_output_phases[..half].copy_from_slice(&self.prev_output_phases[..half]);
+        for (i, x) in self.next_peaks.iter_mut().enumerate() {
+            *x = i;
+        }
+
+        let mut start = 0;
+        for j in 0..self.peaks.len() {
+            let peak = self.peaks[j];
+            // The region of a peak ends at the lowest bin before the next peak.
+            let end = if let Some(&next) = self.peaks.get(j + 1) {
+                (peak..next)
+                    .min_by(|&a, &b| norm(a).partial_cmp(&norm(b)).unwrap_or(Ordering::Equal))
+                    .unwrap()
+                    + 1
             } else {
-                [pre[shifted_bin][0], pre[shifted_bin][1] * pitch]
+                half
             };
+            let region = start..end;
+            start = end;
 
-            let bin_deviation = post[1] - T::from(i).unwrap();
-            let mut phase_diff =
-                bin_deviation * T::from(TAU * slide_size as f64 / len as f64).unwrap();
-            let bin_center_freq = T::from(TAU * i as f64 / len as f64).unwrap();
-            phase_diff = phase_diff + bin_center_freq * T::from(slide_size).unwrap();
+            let freq = self.analysis[peak][1] * pitch;
+            let Some(target) = freq.round().to_usize().filter(|&t| t < half) else {
+                continue;
+            };
 
-            let phase = wrap_phase(prev_output_phases[i] + phase_diff);
-            shifted_spectrum[i] = Complex::from_polar(post[0], phase);
-            prev_output_phases[i] = phase;
+            let prev_peak = if track {
+                self.prev_peaks[target]
+            } else {
+                target
+            };
+            let peak_phase = if self.transient {
+                self.prev_input_phases[peak]
+            } else {
+                wrap_phase(
+                    self.last_output_phases[prev_peak]
+                        + Self::phase_advance(target, freq, len, slide_size),
+                )
+            };
+            let rotation = peak_phase - self.prev_input_phases[peak];
+            for k in region {
+                let Some(i) = (k + target).checked_sub(peak).filter(|&i| i < half) else {
+                    continue;
+                };
+                let phase = self.prev_input_phases[k] + rotation;
+                shifted_spectrum[i] = shifted_spectrum[i] + Complex::from_polar(norm(k), phase);
+                self.next_peaks[i] = target;
+            }
+            self.prev_output_phases[target] = peak_phase;
         }
 
-        fill_right_part_of_spectrum(&mut shifted_spectrum);
-
-        shifted_spectrum
+        // Bins other than the peaks take the phases they are synthesized with.
+        for (i, x) in shifted_spectrum.iter().enumerate().take(half) {
+            if self.next_peaks[i] != i {
+                self.prev_output_phases[i] = x.arg();
+            }
+        }
+        self.prev_peaks.copy_from_slice(&self.next_peaks);
     }
 }
 
@@ -89,3 +467,101 @@ pub fn remove_aliasing<T: Num + Zero + One + Copy, S: Float>(pitch: S, buffer: &
         buffer[nyquist..len - nyquist + 1].fill(T::zero());
     }
 }
+
+/// Same as `remove_aliasing()` but for the `len / 2 + 1` bins of the spectrum of a real signal.
+fn remove_aliasing_half<T: Num + Zero + One + Copy, S: Float>(pitch: S, half_spectrum: &mut [T]) {
+    if pitch < S::one() {
+        let nyquist = (S::from(half_spectrum.len() - 1).unwrap() * pitch)
+            .round()
+            .to_usize()
+            .unwrap();
+        half_spectrum[nyquist..].fill(T::zero());
+    }
+}
+
+#[test]
+fn test() {
+    let len = 64;
+    let slide_size = len / 4;
+    let spectrum: Vec<Complex<f64>> = (0..len)
+        .map(|i| Complex::from_polar(1.0, i as f64 * 0.1))
+        .collect();
+
+    let mut pitch_shifter = PitchShifter::new(len);
+    let first = pitch_shifter.process(&spectrum, 1.5, slide_size);
+    assert!(pitch_shifter.prev_output_phases().iter().any(|&x| x != 0.0));
+
+    // A clone continues with the same phases.
+    let mut cloned = pitch_shifter.clone();
+    assert_eq!(
+        pitch_shifter.process(&spectrum, 1.5, slide_size),
+        cloned.process(&spectrum, 1.5, slide_size)
+    );
+
+    // After reset, it behaves as a new one.
+    pitch_shifter.reset();
+    assert!(pitch_shifter.prev_input_phases().iter().all(|&x| x == 0.0));
+    assert!(pitch_shifter.prev_output_phases().iter().all(|&x| x == 0.0));
+    assert_eq!(pitch_shifter.process(&spectrum, 1.5, slide_size), first);
+}
+
+#[test]
+fn test_bin_mapping() {
+    let len = 64;
+    let slide_size = len / 4;
+    let pitch = 1.5;
+    // A magnitude ramp with stationary phases.
+    let spectrum: Vec<Complex<f64>> = (0..len)
+        .map(|i| Complex::from((i.min(len - i) + 1) as f64))
+        .collect();
+
+    let norms = |bin_mapping| {
+        let options = PitchShiftOptions {
+            bin_mapping,
+            ..Default::default()
+        };
+        let mut pitch_shifter = PitchShifter::with_options(len, options);
+        let shifted = pitch_shifter.process(&spectrum, pitch, slide_size);
+        shifted[1..len / 4]
+            .iter()
+            .map(|x| x.norm())
+            .collect::<Vec<_>>()
+    };
+
+    // Nearest-bin lookup duplicates source bins,
+    // while the other mappings interpolate between them.
+    assert!(norms(BinMapping::Nearest).windows(2).any(|w| w[0] == w[1]));
+    for bin_mapping in [BinMapping::Linear, BinMapping::Sinc] {
+        let norms = norms(bin_mapping);
+        assert!(norms.windows(2).all(|w| w[0] < w[1]), "{:?}", norms);
+    }
+}
+
+#[test]
+fn test_transient() {
+    let len = 64;
+    let slide_size = len / 4;
+    let options = PitchShiftOptions {
+        transient_sensitivity: 0.5,
+        ..Default::default()
+    };
+    let mut pitch_shifter = PitchShifter::with_options(len, options);
+
+    let quiet: Vec<Complex<f64>> = (0..len)
+        .map(|i| Complex::from_polar(0.01, i as f64 * 0.1))
+        .collect();
+    for _ in 0..4 {
+        pitch_shifter.process(&quiet, 1.0, slide_size);
+    }
+    assert!(!pitch_shifter.is_transient());
+
+    let loud: Vec<Complex<f64>> = quiet.iter().map(|x| x * 100.0).collect();
+    let shifted = pitch_shifter.process(&loud, 1.0, slide_size);
+    assert!(pitch_shifter.is_transient());
+    for (x, y) in shifted.iter().zip(&loud).take(len / 2 + 1) {
+        assert!((x - y).norm() < 1e-9);
+    }
+
+    pitch_shifter.process(&loud, 1.0, slide_size);
+    assert!(!pitch_shifter.is_transient());
+}
@@ -0,0 +1,291 @@
+use std::{cmp::Ordering, collections::VecDeque};
+
+use crate::{
+    pitch_detection::{Mpm, PitchDetector},
+    windows, Float,
+};
+
+/// The pitch of a hop, emitted by `PitchTracker`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchRecord<T> {
+    /// Frequency in Hz, or 0 if unvoiced.
+    pub frequency: T,
+    /// Confidence from 0 to 1 given by the detector.
+    pub confidence: T,
+    pub voiced: bool,
+}
+
+impl<T: Float> PitchRecord<T> {
+    /// MIDI note number, fractional, or `None` if unvoiced.
+    pub fn midi_note(&self) -> Option<T> {
+        self.voiced.then(|| {
+            (self.frequency / T::from(440.0).unwrap()).log2() * T::from(12.0).unwrap()
+                + T::from(69.0).unwrap()
+        })
+    }
+}
+
+/// How `PitchTracker` smooths the frequencies over hops.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Smoothing {
+    #[default]
+    Off,
+    /// Median of the frequencies of the given number of latest voiced hops.
+    Median(usize),
+    /// Picks the detected frequency or one of its octaves, penalizing pitch jumps
+    /// within a voiced segment. This mainly fixes octave errors.
+    ///
+    /// It is the forward pass of the Viterbi algorithm, taking the most probable
+    /// candidate at each hop without backtracking, so it adds no delay.
+    OctaveCorrection,
+}
+
+/// Tracks the pitch window by window with a `PitchDetector`.
+pub struct PitchTracker<T: Float, D: PitchDetector<T>> {
+    detector: D,
+    sample_rate: T,
+    pub smoothing: Smoothing,
+    /// An unvoiced hop becomes voiced if the confidence is above this.
+    pub voicing_on: T,
+    /// A voiced hop becomes unvoiced if the confidence is below this.
+    pub voicing_off: T,
+    /// Penalty of `Smoothing::OctaveCorrection` per octave of pitch change.
+    pub jump_cost: T,
+    voiced: bool,
+    history: VecDeque<T>,
+    sorted: Vec<T>,
+    /// Frequencies and probabilities of the candidates of `Smoothing::OctaveCorrection`.
+    states: Vec<(T, T)>,
+}
+
+impl<T: Float, D: PitchDetector<T>> PitchTracker<T, D> {
+    /// Without smoothing and hysteresis, a hop is voiced if the detector finds a pitch.
+    pub fn new(detector: D, sample_rate: u32) -> Self {
+        PitchTracker {
+            detector,
+            sample_rate: T::from(sample_rate).unwrap(),
+            smoothing: Smoothing::Off,
+            voicing_on: T::zero(),
+            voicing_off: T::zero(),
+            jump_cost: T::from(4.0).unwrap(),
+            voiced: false,
+            history: VecDeque::new(),
+            sorted: Vec::new(),
+            states: Vec::with_capacity(3),
+        }
+    }
+
+    pub fn detector(&self) -> &D {
+        &self.detector
+    }
+
+    pub fn detector_mut(&mut self) -> &mut D {
+        &mut self.detector
+    }
+
+    pub fn reset(&mut self) {
+        self.voiced = false;
+        self.history.clear();
+        self.states.clear();
+    }
+
+    /// Detect the pitch of the window of the next hop.
+    pub fn process(&mut self, buf: &[T]) -> PitchRecord<T> {
+        let detection = self
+            .detector
+            .detect(buf)
+            .map(|(wavelength, confidence)| (self.sample_rate / wavelength, confidence));
+        let confidence = detection.map_or(T::zero(), |d| d.1);
+
+        // Hysteresis
+        self.voiced = detection.is_some()
+            && if self.voiced {
+                self.voicing_off <= confidence
+            } else {
+                self.voicing_on < confidence
+            };
+        let raw_frequency = detection.map_or(T::zero(), |d| d.0);
+        let voiced_frequency = if self.voiced {
+            raw_frequency
+        } else {
+            T::zero()
+        };
+
+        let frequency = match self.smoothing {
+            Smoothing::Off => voiced_frequency,
+            Smoothing::Median(len) => self.median(voiced_frequency, len),
+            Smoothing::OctaveCorrection => self.correct_octave(voiced_frequency, confidence),
+        };
+
+        PitchRecord {
+            frequency,
+            confidence,
+            voiced: frequency != T::zero(),
+        }
+    }
+
+    fn median(&mut self, frequency: T, len: usize) -> T {
+        if frequency == T::zero() {
+            self.history.clear();
+            return frequency;
+        }
+        self.history.push_back(frequency);
+        while len.max(1) < self.history.len() {
+            self.history.pop_front();
+        }
+
+        self.sorted.clear();
+        self.sorted.extend(self.history.iter().copied());
+        self.sorted
+            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        self.sorted[self.sorted.len() / 2]
+    }
+
+    fn correct_octave(&mut self, frequency: T, confidence: T) -> T {
+        if frequency == T::zero() {
+            self.states.clear();
+            return frequency;
+        }
+
+        let two = T::from(2).unwrap();
+        let octave = T::from(0.2).unwrap();
+        let confidence = confidence.min(T::one());
+        // Candidates and their observation probabilities.
+        let candidates = [
+            (frequency, confidence),
+            (frequency / two, confidence * octave),
+            (frequency * two, confidence * octave),
+        ];
+
+        let mut next = candidates.map(|(f, observation)| {
+            // A voiced segment starts from any candidate.
+            let transition = if self.states.is_empty() {
+                T::one()
+            } else {
+                self.states.iter().fold(T::zero(), |a, &(prev_f, p)| {
+                    a.max(p * (-(f / prev_f).log2().abs() * self.jump_cost).exp())
+                })
+            };
+            (f, transition * observation)
+        });
+
+        // Normalize not to underflow.
+        let sum = next.iter().fold(T::zero(), |a, s| a + s.1);
+        if T::zero() < sum {
+            for s in &mut next {
+                s.1 = s.1 / sum;
+            }
+        }
+        self.states.clear();
+        self.states.extend_from_slice(&next);
+
+        self.states
+            .iter()
+            .fold((T::zero(), T::neg_infinity()), |a, &s| {
+                if a.1 < s.1 {
+                    s
+                } else {
+                    a
+                }
+            })
+            .0
+    }
+}
+
+impl<T: Float> PitchTracker<T, Mpm<T>> {
+    /// The tracker used by `api::pitch_correct()`, for voices up to 2200 Hz.
+    pub fn with_mpm(window_size: usize, sample_rate: u32) -> Self {
+        let min_wavelength = T::from(sample_rate).unwrap() / T::from(440.0 * 5.0).unwrap();
+        let detector = Mpm::new(
+            windows::rectangular_window(window_size),
+            min_wavelength,
+            T::from(0.4).unwrap(),
+        );
+        PitchTracker::new(detector, sample_rate)
+    }
+}
+
+/// Detect the pitch of `buf` every `slide_size` samples at once.
+///
+/// Unlike `PitchTracker`, this uses `PitchDetector::detect_frames()`,
+/// so detectors such as `Pyin` can smooth over the whole signal.
+/// The `i`th record is of the window starting at `i * slide_size`.
+pub fn pitch_contour<T: Float, D: PitchDetector<T>>(
+    detector: &mut D,
+    sample_rate: u32,
+    window_size: usize,
+    slide_size: usize,
+    buf: &[T],
+) -> Vec<PitchRecord<T>> {
+    let sample_rate = T::from(sample_rate).unwrap();
+    let frames: Vec<_> = buf.windows(window_size).step_by(slide_size).collect();
+    detector
+        .detect_frames(&frames)
+        .into_iter()
+        .map(|detection| match detection {
+            Some((wavelength, confidence)) => PitchRecord {
+                frequency: sample_rate / wavelength,
+                confidence,
+                voiced: true,
+            },
+            None => PitchRecord {
+                frequency: T::zero(),
+                confidence: T::zero(),
+                voiced: false,
+            },
+        })
+        .collect()
+}
+
+#[test]
+fn test() {
+    struct Scripted(std::vec::IntoIter<Option<(f64, f64)>>);
+
+    impl PitchDetector<f64> for Scripted {
+        fn detect(&mut self, _: &[f64]) -> Option<(f64, f64)> {
+            self.0.next().unwrap()
+        }
+    }
+
+    let sample_rate = 16000;
+    // 200 Hz with an octave error, a weak frame and a gap.
+    let script = vec![
+        Some((80.0, 0.9)),
+        Some((80.0, 0.9)),
+        Some((40.0, 0.8)),
+        Some((80.0, 0.9)),
+        Some((80.0, 0.3)),
+        Some((80.0, 0.9)),
+        None,
+        None,
+        Some((80.0, 0.9)),
+    ];
+    let track = |smoothing| {
+        let mut tracker = PitchTracker::new(Scripted(script.clone().into_iter()), sample_rate);
+        tracker.smoothing = smoothing;
+        tracker.voicing_on = 0.5;
+        tracker.voicing_off = 0.2;
+        (0..script.len())
+            .map(|_| tracker.process(&[]).frequency)
+            .collect::<Vec<_>>()
+    };
+
+    let f = 200.0;
+    assert_eq!(track(Smoothing::Off), [f, f, f * 2.0, f, f, f, 0.0, 0.0, f]);
+    assert_eq!(track(Smoothing::Median(3)), [f, f, f, f, f, f, 0.0, 0.0, f]);
+    assert_eq!(
+        track(Smoothing::OctaveCorrection),
+        [f, f, f, f, f, f, 0.0, 0.0, f]
+    );
+
+    let contour = pitch_contour(
+        &mut Scripted(script.clone().into_iter()),
+        sample_rate,
+        4,
+        2,
+        &[0.0; 20],
+    );
+    assert_eq!(contour.len(), script.len());
+    assert_eq!(contour[0].midi_note().map(f64::round), Some(55.0));
+    assert_eq!(contour[6].midi_note(), None);
+}
@@ -0,0 +1,247 @@
+//! Sample-rate conversion with a windowed-sinc low-pass filter.
+
+use crate::Float;
+
+/// Trade-off between the speed and the accuracy of `Resampler`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// 16 taps, passband up to 85% of the Nyquist frequency.
+    Fast,
+    /// 64 taps, passband up to 92% of the Nyquist frequency.
+    #[default]
+    Standard,
+    /// 128 taps, passband up to 96% of the Nyquist frequency.
+    Best,
+}
+
+impl Quality {
+    /// Half the number of taps, the relative cutoff and the beta of the Kaiser window.
+    fn parameters(&self) -> (usize, f64, f64) {
+        match self {
+            Quality::Fast => (8, 0.85, 6.0),
+            Quality::Standard => (32, 0.92, 8.6),
+            Quality::Best => (64, 0.96, 12.0),
+        }
+    }
+}
+
+/// Number of phases of the filter table per input sample.
+/// The filter between the phases is interpolated linearly.
+const PHASES: usize = 256;
+
+/// Streaming sample-rate converter with a polyphase windowed-sinc filter.
+///
+/// `ratio` is the output sample rate divided by the input sample rate and can be any positive number.
+/// When downsampling, the cutoff goes down with the ratio so that nothing aliases.
+/// The output is aligned with the input: the `i`th output sample is at the time of input sample `i / ratio`.
+///
+/// # Example
+/// ```
+/// # use voiche::resample::{Quality, Resampler};
+/// let mut resampler = Resampler::<f32>::from_rates(44100, 48000, Quality::Standard);
+/// let mut output = Vec::new();
+/// for block in vec![0.0; 44100].chunks(512) {
+///     resampler.process(block, &mut output);
+/// }
+/// resampler.finish(&mut output);
+/// assert_eq!(output.len(), 48000);
+/// ```
+pub struct Resampler<T: Float> {
+    /// Kept in f64 so that the timing stays exact over long streams.
+    ratio: f64,
+    /// Half the length of the filter in input samples.
+    half_len: usize,
+    /// Filter values at every `1 / PHASES` input samples from the center.
+    table: Vec<T>,
+    /// Input samples from `offset`, with `half_len` zeros before the first one.
+    history: Vec<T>,
+    offset: usize,
+    input_len: usize,
+    output_len: usize,
+}
+
+impl<T: Float> Resampler<T> {
+    pub fn new(ratio: T, quality: Quality) -> Self {
+        Self::with_ratio(ratio.to_f64().unwrap(), quality)
+    }
+
+    pub fn from_rates(input_rate: u32, output_rate: u32, quality: Quality) -> Self {
+        Self::with_ratio(output_rate as f64 / input_rate as f64, quality)
+    }
+
+    fn with_ratio(ratio: f64, quality: Quality) -> Self {
+        assert!(0.0 < ratio);
+
+        let (half_taps, rolloff, beta) = quality.parameters();
+        // The filter widens when downsampling to keep its steepness.
+        let scale = ratio.min(1.0);
+        let cutoff = rolloff * scale;
+        let half_len = (half_taps as f64 / scale).ceil() as usize;
+
+        let table = (0..=half_len * PHASES + 1)
+            .map(|i| {
+                let x = i as f64 / PHASES as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    let x = std::f64::consts::PI * cutoff * x;
+                    x.sin() / x
+                };
+                T::from(cutoff * sinc * kaiser(x / half_len as f64, beta)).unwrap()
+            })
+            .collect();
+
+        Resampler {
+            ratio,
+            half_len,
+            table,
+            history: vec![T::zero(); half_len],
+            offset: 0,
+            input_len: 0,
+            output_len: 0,
+        }
+    }
+
+    pub fn ratio(&self) -> T {
+        T::from(self.ratio).unwrap()
+    }
+
+    /// Number of input samples needed after an input sample before it is fully output.
+    pub fn latency(&self) -> usize {
+        self.half_len
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize(self.half_len, T::zero());
+        self.offset = 0;
+        self.input_len = 0;
+        self.output_len = 0;
+    }
+
+    /// Append `input` and push the output samples which are ready to `output`.
+    pub fn process(&mut self, input: &[T], output: &mut Vec<T>) {
+        self.history.extend_from_slice(input);
+        self.input_len += input.len();
+        self.render(output, usize::MAX);
+    }
+
+    /// Flush the rest of the output, which makes `input_len * ratio` samples in total,
+    /// and start over.
+    pub fn finish(&mut self, output: &mut Vec<T>) {
+        // Tolerate the rounding error of the ratio, e.g. 44100 * (48000 / 44100).
+        let total = self.input_len as f64 * self.ratio;
+        let total = (total - total * 1e-12).ceil() as usize;
+        self.history
+            .resize(self.history.len() + self.half_len + 1, T::zero());
+        self.render(output, total);
+        self.reset();
+    }
+
+    fn render(&mut self, output: &mut Vec<T>, total: usize) {
+        let phases = T::from(PHASES).unwrap();
+        while self.output_len < total {
+            // The time of the output sample in `history`.
+            let time =
+                self.output_len as f64 / self.ratio + self.half_len as f64 - self.offset as f64;
+            let center = time.floor() as usize;
+            if self.history.len() <= center + self.half_len {
+                break;
+            }
+
+            let fract = T::from(time - center as f64).unwrap();
+            let mut y = T::zero();
+            for (n, &x) in self.history[center + 1 - self.half_len..=center + self.half_len]
+                .iter()
+                .enumerate()
+            {
+                let distance = (T::from(n as isize + 1 - self.half_len as isize).unwrap() - fract)
+                    .abs()
+                    * phases;
+                let i = distance.floor().to_usize().unwrap();
+                let a = distance - T::from(i).unwrap();
+                let h = self.table[i] + (self.table[i + 1] - self.table[i]) * a;
+                y = y + x * h;
+            }
+            output.push(y);
+            self.output_len += 1;
+
+            // Drop the samples no longer needed.
+            let consumed = center + 1 - self.half_len;
+            if 4096 <= consumed {
+                self.history.drain(..consumed);
+                self.offset += consumed;
+            }
+        }
+    }
+}
+
+/// Kaiser window at `x` from -1 to 1.
+fn kaiser(x: f64, beta: f64) -> f64 {
+    if 1.0 < x.abs() {
+        return 0.0;
+    }
+    bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / 2.0 / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Resample `buf` at once to `buf.len() * ratio` samples.
+/// `ratio` is the output sample rate divided by the input sample rate.
+pub fn resample<T: Float>(buf: &[T], ratio: T, quality: Quality) -> Vec<T> {
+    let mut resampler = Resampler::new(ratio, quality);
+    let mut output = Vec::new();
+    resampler.process(buf, &mut output);
+    resampler.finish(&mut output);
+    output
+}
+
+#[test]
+fn test() {
+    let tone = |f: f64, sample_rate: f64, len: usize| {
+        (0..len)
+            .map(|i| (std::f64::consts::TAU * f * i as f64 / sample_rate).sin())
+            .collect::<Vec<_>>()
+    };
+    let max_error = |a: &[f64], b: &[f64]| {
+        a.iter()
+            .zip(b)
+            .skip(200)
+            .take(a.len().min(b.len()) - 400)
+            .fold(0.0f64, |m, (x, y)| m.max((x - y).abs()))
+    };
+
+    // 1 kHz from 44.1 kHz to 48 kHz and back.
+    let input = tone(1000.0, 44100.0, 4410);
+    let output = resample(&input, 48000.0 / 44100.0, Quality::Standard);
+    assert_eq!(output.len(), 4800);
+    assert!(max_error(&output, &tone(1000.0, 48000.0, 4800)) < 1e-3);
+    let output = resample(&output, 44100.0 / 48000.0, Quality::Standard);
+    assert_eq!(output.len(), 4410);
+    assert!(max_error(&output, &input) < 1e-3);
+
+    // 15 kHz is above the Nyquist frequency of 16 kHz and is filtered out instead of aliasing.
+    let output = resample(&tone(15000.0, 48000.0, 4800), 1.0 / 3.0, Quality::Standard);
+    assert_eq!(output.len(), 1600);
+    assert!(max_error(&output, &[0.0; 1600]) < 1e-3);
+
+    // Streaming in odd blocks gives the same output.
+    let mut resampler = Resampler::new(0.7, Quality::Fast);
+    let mut streamed = Vec::new();
+    for block in input.chunks(37) {
+        resampler.process(block, &mut streamed);
+    }
+    resampler.finish(&mut streamed);
+    assert_eq!(streamed, resample(&input, 0.7, Quality::Fast));
+}
@@ -0,0 +1,112 @@
+use crate::Float;
+
+/// A fixed-capacity FIFO of samples.
+///
+/// All operations work in place, so no allocation happens after `new()`.
+#[derive(Clone)]
+pub struct RingBuffer<T: Float> {
+    buffer: Vec<T>,
+    head: usize,
+    len: usize,
+}
+
+impl<T: Float> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            buffer: vec![T::zero(); capacity],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Append samples to the back.
+    /// Panics if the capacity is exceeded.
+    pub fn push_slice(&mut self, slice: &[T]) {
+        let (a, b) = self.reserve(slice.len());
+        let (sa, sb) = slice.split_at(a.len());
+        a.copy_from_slice(sa);
+        b.copy_from_slice(sb);
+    }
+
+    /// Append `size` zeros to the back.
+    pub fn push_zeros(&mut self, size: usize) {
+        let (a, b) = self.reserve(size);
+        a.fill(T::zero());
+        b.fill(T::zero());
+    }
+
+    /// Copy samples starting at `offset` from the front without consuming them.
+    pub fn copy_to_slice(&self, offset: usize, slice: &mut [T]) {
+        let (a, b) = self.range(offset, slice.len());
+        let (sa, sb) = slice.split_at_mut(a.len());
+        sa.copy_from_slice(a);
+        sb.copy_from_slice(b);
+    }
+
+    /// Add `slice` onto the samples starting at `offset` from the front.
+    pub fn add_slice(&mut self, offset: usize, slice: &[T]) {
+        let (a, b) = self.range_mut(offset, slice.len());
+        for (x, &y) in a.iter_mut().chain(b.iter_mut()).zip(slice) {
+            *x = *x + y;
+        }
+    }
+
+    /// Move samples from the front into `slice`.
+    pub fn pop_slice(&mut self, slice: &mut [T]) {
+        self.copy_to_slice(0, slice);
+        self.discard(slice.len());
+    }
+
+    /// Drop `size` samples from the front.
+    pub fn discard(&mut self, size: usize) {
+        assert!(size <= self.len);
+        self.head = (self.head + size) % self.capacity().max(1);
+        self.len -= size;
+    }
+
+    fn reserve(&mut self, size: usize) -> (&mut [T], &mut [T]) {
+        assert!(
+            self.len + size <= self.capacity(),
+            "ring buffer overflow: {} + {} > {}",
+            self.len,
+            size,
+            self.capacity()
+        );
+        let offset = self.len;
+        self.len += size;
+        self.range_mut(offset, size)
+    }
+
+    fn range(&self, offset: usize, size: usize) -> (&[T], &[T]) {
+        assert!(offset + size <= self.len);
+        let start = (self.head + offset) % self.capacity().max(1);
+        let first = size.min(self.capacity() - start);
+        let (left, right) = self.buffer.split_at(start);
+        (&right[..first], &left[..size - first])
+    }
+
+    fn range_mut(&mut self, offset: usize, size: usize) -> (&mut [T], &mut [T]) {
+        assert!(offset + size <= self.len);
+        let start = (self.head + offset) % self.capacity().max(1);
+        let first = size.min(self.capacity() - start);
+        let (left, right) = self.buffer.split_at_mut(start);
+        (&mut right[..first], &mut left[..size - first])
+    }
+}
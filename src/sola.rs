@@ -0,0 +1,281 @@
+//! Time-domain processing by overlap-adding segments of the waveform.
+//!
+//! These avoid the phasiness of the phase vocoder and are cheap,
+//! but work best on monophonic signals such as speech.
+
+use crate::{pitch_detection::PitchMark, transform::buffer_overlapping_write, windows, Float};
+
+/// WSOLA (waveform similarity overlap-add) time stretcher.
+///
+/// Like `time_stretch::TimeStretcher`, segments are taken from the input every
+/// `slide_size / time_rate` samples and written to the output every `slide_size` samples,
+/// but each segment is moved within `tolerance` samples
+/// to the position most similar to the continuation of the previous segment.
+///
+/// It is used like `transform::Transformer`.
+#[derive(Clone)]
+pub struct Wsola<T: Float> {
+    pub time_rate: T,
+    window: Vec<T>,
+    slide_size: usize,
+    tolerance: usize,
+    /// Absolute position of the first sample of `input_buffer`.
+    input_offset: usize,
+    /// Ideal position of the next segment.
+    input_position: T,
+    /// Position of the previous segment.
+    prev_position: Option<usize>,
+    input_buffer: Vec<T>,
+    output_buffer: Vec<T>,
+    segment: Vec<T>,
+}
+
+impl<T: Float> Wsola<T> {
+    /// `slide_size` should be at most half of `window_size`, and `time_rate` must be positive.
+    pub fn new(window_size: usize, slide_size: usize, tolerance: usize, time_rate: T) -> Self {
+        assert!(0 < slide_size && slide_size <= window_size);
+        assert!(T::zero() < time_rate, "time_rate must be positive");
+        let window = windows::hann_window(window_size);
+        let scale = T::from(slide_size).unwrap() / window.iter().fold(T::zero(), |a, &x| a + x);
+        Wsola {
+            time_rate,
+            window: window.into_iter().map(|x| x * scale).collect(),
+            slide_size,
+            tolerance,
+            input_offset: 0,
+            input_position: T::from(tolerance).unwrap(),
+            prev_position: None,
+            input_buffer: vec![T::zero(); window_size - slide_size + tolerance],
+            output_buffer: vec![],
+            segment: vec![T::zero(); window_size],
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    /// Delay in input samples before the output starts.
+    pub fn latency(&self) -> usize {
+        self.window_size() - self.slide_size
+    }
+
+    pub fn input_slice(&mut self, slice: &[T]) {
+        self.input_buffer.extend_from_slice(slice);
+    }
+
+    pub fn output_slice_exact(&mut self, slice: &mut [T]) -> bool {
+        let overlap_size = self.window_size() - self.slide_size;
+        if self.output_buffer.len() >= slice.len() + overlap_size {
+            slice.copy_from_slice(&self.output_buffer[..slice.len()]);
+            self.output_buffer.drain(0..slice.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Flush the remaining input and append all the remaining output to `vec`.
+    pub fn finish(mut self, vec: &mut Vec<T>) {
+        let len = self.input_buffer.len();
+        self.input_buffer
+            .resize(len + self.window_size() + self.tolerance * 2, T::zero());
+        self.process();
+        vec.extend_from_slice(&self.output_buffer);
+    }
+
+    pub fn process(&mut self) {
+        assert!(T::zero() < self.time_rate, "time_rate must be positive");
+        let window_size = self.window_size();
+
+        loop {
+            let ideal = self.input_position.round().to_usize().unwrap();
+            let natural = self.prev_position.map(|p| p + self.slide_size);
+            let end = (ideal + self.tolerance).max(natural.unwrap_or(0)) + window_size;
+            if self.input_offset + self.input_buffer.len() < end {
+                break;
+            }
+
+            let position = match natural {
+                Some(natural) => self.most_similar(ideal, natural),
+                None => ideal,
+            };
+            let start = position - self.input_offset;
+            for ((s, &x), &w) in self
+                .segment
+                .iter_mut()
+                .zip(&self.input_buffer[start..])
+                .zip(&self.window)
+            {
+                *s = x * w;
+            }
+            buffer_overlapping_write(self.slide_size, &mut self.output_buffer, &self.segment);
+            self.prev_position = Some(position);
+
+            self.input_position =
+                self.input_position + T::from(self.slide_size).unwrap() / self.time_rate;
+
+            // Drop the samples that are no longer needed.
+            let next = self.input_position.round().to_usize().unwrap();
+            let keep = next
+                .saturating_sub(self.tolerance)
+                .min(position + self.slide_size);
+            let discard = keep - self.input_offset;
+            self.input_buffer.drain(0..discard);
+            self.input_offset = keep;
+        }
+    }
+
+    /// Find the position around `ideal` whose segment correlates most with the one at `natural`.
+    fn most_similar(&self, ideal: usize, natural: usize) -> usize {
+        let window_size = self.window_size();
+        let natural = &self.input_buffer[natural - self.input_offset..][..window_size];
+        let start = ideal.saturating_sub(self.tolerance).max(self.input_offset);
+
+        (start..=ideal + self.tolerance)
+            .map(|position| {
+                let candidate = &self.input_buffer[position - self.input_offset..][..window_size];
+                let correlation = candidate
+                    .iter()
+                    .zip(natural)
+                    .fold(T::zero(), |a, (&x, &y)| a + x * y);
+                (position, correlation)
+            })
+            .fold(
+                (ideal, T::neg_infinity()),
+                |a, b| if a.1 < b.1 { b } else { a },
+            )
+            .0
+    }
+}
+
+/// Change the duration of `buf` by `time_rate` with WSOLA.
+///
+/// The output has `buf.len() * time_rate` samples.
+pub fn wsola<T: Float>(
+    window_size: usize,
+    slide_size: usize,
+    tolerance: usize,
+    time_rate: T,
+    buf: &[T],
+) -> Vec<T> {
+    let mut wsola = Wsola::new(window_size, slide_size, tolerance, time_rate);
+    let latency = wsola.latency();
+    wsola.input_slice(buf);
+    let mut output = Vec::new();
+    wsola.finish(&mut output);
+
+    let start = (T::from(latency).unwrap() * time_rate)
+        .round()
+        .to_usize()
+        .unwrap();
+    let len = (T::from(buf.len()).unwrap() * time_rate)
+        .round()
+        .to_usize()
+        .unwrap();
+    output.drain(..start.min(output.len()));
+    output.resize(len, T::zero());
+    output
+}
+
+/// Shift the pitch of `buf` with PSOLA (pitch-synchronous overlap-add).
+///
+/// A segment two periods long is cut out around each of `marks`
+/// and they are overlap-added `pitch` times as densely.
+/// Unvoiced marks are copied as they are. The output has the same length as `buf`.
+pub fn psola<T: Float>(buf: &[T], marks: &[PitchMark<T>], pitch: T) -> Vec<T> {
+    let mut output = vec![T::zero(); buf.len()];
+    let mut weights = vec![T::zero(); buf.len()];
+    if marks.is_empty() {
+        return buf.to_vec();
+    }
+
+    let mut time = T::from(marks[0].position).unwrap();
+    let mut j = 0;
+    while let Some(position) = time.round().to_usize().filter(|&p| p < buf.len()) {
+        // The analysis mark nearest to the synthesis mark.
+        while j + 1 < marks.len()
+            && marks[j + 1].position.abs_diff(position) < marks[j].position.abs_diff(position)
+        {
+            j += 1;
+        }
+        let mark = marks[j];
+        let period = if mark.voiced {
+            mark.wavelength / pitch
+        } else {
+            mark.wavelength
+        };
+
+        // Lowering the pitch needs longer segments not to leave gaps.
+        let half = mark
+            .wavelength
+            .max(period)
+            .round()
+            .to_usize()
+            .unwrap()
+            .max(1);
+        for d in 0..half * 2 + 1 {
+            let (Some(src), Some(dst)) = (
+                (mark.position + d).checked_sub(half),
+                (position + d).checked_sub(half),
+            ) else {
+                continue;
+            };
+            if buf.len() <= src || buf.len() <= dst {
+                continue;
+            }
+            let x = T::from(d as f64 / half as f64 - 1.0).unwrap();
+            let w = (T::one() + (x * T::PI()).cos()) / T::from(2).unwrap();
+            output[dst] = output[dst] + buf[src] * w;
+            weights[dst] = weights[dst] + w;
+        }
+
+        time = time + period;
+    }
+
+    for (y, &w) in output.iter_mut().zip(&weights) {
+        *y = *y / w.max(T::one());
+    }
+    output
+}
+
+#[test]
+fn test() {
+    use crate::test_util::{detect, tone};
+
+    let f0 = 200.0;
+    let buf = tone(f0, 16000);
+    let rms = |b: &[f64]| (b.iter().map(|x| x * x).sum::<f64>() / b.len() as f64).sqrt();
+
+    for time_rate in [0.7, 1.4] {
+        let output = wsola(320, 160, 80, time_rate, &buf);
+        assert_eq!(
+            output.len(),
+            (buf.len() as f64 * time_rate).round() as usize
+        );
+        let middle = &output[1000..output.len() - 1000];
+        assert!((rms(middle) / rms(&buf) - 1.0).abs() < 0.1);
+        // The middle of the output.
+        assert!((detect(&output, time_rate / 2.0) - f0).abs() < 5.0);
+    }
+
+    let fft = crate::fft::Fft::new(1024);
+    let window = windows::hann_window(1024);
+    let marks = crate::pitch_detection::pitch_marks(&fft, &window, &buf, 256, 20.0, 0.5);
+    assert!(marks.iter().all(|m| m.voiced));
+    for pitch in [0.8, 1.25] {
+        let output = psola(&buf, &marks, pitch);
+        assert_eq!(output.len(), buf.len());
+        assert!((detect(&output, 0.5) - f0 * pitch).abs() < 5.0);
+    }
+}
+
+#[test]
+#[should_panic(expected = "time_rate must be positive")]
+fn test_zero_time_rate() {
+    Wsola::new(64, 16, 8, 0.0);
+}
@@ -0,0 +1,419 @@
+use crate::{
+    fft::{expand_half_spectrum, fold_half_spectrum, Fft},
+    num_complex::Complex,
+    windows::{self, WindowError},
+    Float,
+};
+
+/// A process which modifies a spectrum in place, frame by frame.
+///
+/// Closures of `FnMut(&mut [Complex<T>])` are also spectral processors,
+/// and processors can be connected with `chain()`.
+pub trait SpectralProcessor<T: Float> {
+    fn process(&mut self, spectrum: &mut [Complex<T>]);
+
+    /// Process the `len / 2 + 1` bins of the spectrum of a real signal, as the drivers do.
+    ///
+    /// `spectrum` is a work buffer of the window size.
+    /// By default the full spectrum is rebuilt in it and passed to `process()`.
+    /// Processors which only need the lower half override this to skip the copies.
+    fn process_half(&mut self, half_spectrum: &mut [Complex<T>], spectrum: &mut [Complex<T>]) {
+        expand_half_spectrum(half_spectrum, spectrum);
+        self.process(spectrum);
+        fold_half_spectrum(spectrum, half_spectrum);
+    }
+
+    /// Clear the internal state, e.g. carried-over phases.
+    fn reset(&mut self) {}
+
+    /// Additional delay in samples introduced by this processor.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Ratio by which this processor moves frequencies, e.g. the pitch of a pitch shifter.
+    /// `MultiSpectralDriver` uses it to find where each output bin came from.
+    fn frequency_ratio(&self) -> T {
+        T::one()
+    }
+
+    fn chain<P: SpectralProcessor<T>>(self, other: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain(self, other)
+    }
+}
+
+impl<T: Float, F: FnMut(&mut [Complex<T>])> SpectralProcessor<T> for F {
+    fn process(&mut self, spectrum: &mut [Complex<T>]) {
+        self(spectrum)
+    }
+}
+
+/// Two processors applied in sequence. Created by `SpectralProcessor::chain()`.
+#[derive(Clone)]
+pub struct Chain<A, B>(pub A, pub B);
+
+impl<T: Float, A: SpectralProcessor<T>, B: SpectralProcessor<T>> SpectralProcessor<T>
+    for Chain<A, B>
+{
+    fn process(&mut self, spectrum: &mut [Complex<T>]) {
+        self.0.process(spectrum);
+        self.1.process(spectrum);
+    }
+
+    fn process_half(&mut self, half_spectrum: &mut [Complex<T>], spectrum: &mut [Complex<T>]) {
+        self.0.process_half(half_spectrum, spectrum);
+        self.1.process_half(half_spectrum, spectrum);
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+        self.1.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.0.latency() + self.1.latency()
+    }
+
+    fn frequency_ratio(&self) -> T {
+        self.0.frequency_ratio() * self.1.frequency_ratio()
+    }
+}
+
+/// Runs a `SpectralProcessor` on windows of a signal.
+///
+/// This does the same thing as `api::retouch_spectrum()`,
+/// but `post_window` is normalized and all buffers are allocated once in `new()`.
+#[derive(Clone)]
+pub struct SpectralDriver<T: Float, P: SpectralProcessor<T>> {
+    fft: Fft<T>,
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    spectrum: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+    processor: P,
+}
+
+impl<T: Float, P: SpectralProcessor<T>> SpectralDriver<T, P> {
+    /// `post_window` is normalized so that the output is the input if `processor` does nothing.
+    /// Panics if the windows can't reconstruct a signal, see `try_new()`.
+    pub fn new(pre_window: Vec<T>, post_window: Vec<T>, slide_size: usize, processor: P) -> Self {
+        match Self::try_new(pre_window, post_window, slide_size, processor) {
+            Ok(x) => x,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Same as `new()` but returns an error if the windows can't reconstruct a signal,
+    /// see `windows::check_reconstruction()`.
+    pub fn try_new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        processor: P,
+    ) -> Result<Self, WindowError> {
+        let post_window = windows::check_reconstruction(&pre_window, &post_window, slide_size)?
+            .normalize(&post_window);
+
+        let window_size = pre_window.len();
+        let fft = Fft::new(window_size);
+        let scratch = vec![Complex::from(T::zero()); fft.scratch_len()];
+        let half_spectrum = vec![Complex::from(T::zero()); fft.half_len()];
+        Ok(SpectralDriver {
+            fft,
+            pre_window,
+            post_window,
+            slide_size,
+            buffer: vec![T::zero(); window_size],
+            half_spectrum,
+            spectrum: vec![Complex::from(T::zero()); window_size],
+            scratch,
+            processor,
+        })
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.pre_window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    pub fn into_processor(self) -> P {
+        self.processor
+    }
+
+    pub fn reset(&mut self) {
+        self.processor.reset();
+    }
+
+    /// Delay in samples between the input and the output of a `Transformer` driven by this.
+    pub fn latency(&self) -> usize {
+        self.window_size() - self.slide_size + self.processor.latency()
+    }
+
+    /// Process a window. `input` and `output` must have the window size.
+    pub fn process(&mut self, input: &[T], output: &mut [T]) {
+        for ((b, &x), &w) in self.buffer.iter_mut().zip(input).zip(&self.pre_window) {
+            *b = x * w;
+        }
+        self.fft.forward_real_with_scratch(
+            &mut self.buffer,
+            &mut self.half_spectrum,
+            &mut self.scratch,
+        );
+        self.processor
+            .process_half(&mut self.half_spectrum, &mut self.spectrum);
+        self.fft.inverse_real_with_scratch(
+            &mut self.half_spectrum,
+            &mut self.buffer,
+            &mut self.scratch,
+        );
+
+        let scale = T::one() / T::from(self.buffer.len()).unwrap();
+        for ((y, &x), &w) in output.iter_mut().zip(&self.buffer).zip(&self.post_window) {
+            *y = x * scale * w;
+        }
+    }
+
+    /// Same as `process()` but returns a new `Vec`, for `transform()` and `Transformer`.
+    pub fn process_to_vec(&mut self, input: &[T]) -> Vec<T> {
+        let mut output = vec![T::zero(); input.len()];
+        self.process(input, &mut output);
+        output
+    }
+}
+
+/// How `MultiSpectralDriver` processes the channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Linking {
+    /// Each channel has its own processor.
+    #[default]
+    Independent,
+    /// One processor processes a mix of the channels,
+    /// and each channel follows the result keeping its own level and phase relative to the mix.
+    /// The pitch, the envelope and the phase evolution are shared,
+    /// so the stereo image is kept.
+    Linked,
+}
+
+/// Runs `SpectralProcessor`s on windows of several channels.
+#[derive(Clone)]
+pub struct MultiSpectralDriver<T: Float, P: SpectralProcessor<T>> {
+    fft: Fft<T>,
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    linking: Linking,
+    buffer: Vec<T>,
+    half_spectra: Vec<Vec<Complex<T>>>,
+    /// The mix for `Linking::Linked`, and its processed version.
+    half_mix: Vec<Complex<T>>,
+    half_processed: Vec<Complex<T>>,
+    spectrum: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+    /// One per channel for `Linking::Independent`, otherwise one.
+    processors: Vec<P>,
+}
+
+impl<T: Float, P: SpectralProcessor<T> + Clone> MultiSpectralDriver<T, P> {
+    /// Panics if the windows can't reconstruct a signal, see `try_new()`.
+    pub fn new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        channels: usize,
+        linking: Linking,
+        processor: P,
+    ) -> Self {
+        match Self::try_new(
+            pre_window,
+            post_window,
+            slide_size,
+            channels,
+            linking,
+            processor,
+        ) {
+            Ok(x) => x,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Same as `new()` but returns an error if the windows can't reconstruct a signal,
+    /// see `windows::check_reconstruction()`.
+    pub fn try_new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        channels: usize,
+        linking: Linking,
+        processor: P,
+    ) -> Result<Self, WindowError> {
+        let post_window = windows::check_reconstruction(&pre_window, &post_window, slide_size)?
+            .normalize(&post_window);
+        assert!(0 < channels);
+
+        let window_size = pre_window.len();
+        let fft = Fft::new(window_size);
+        let processors = match linking {
+            Linking::Independent => vec![processor; channels],
+            Linking::Linked => vec![processor],
+        };
+        let zero = Complex::from(T::zero());
+        let half_len = fft.half_len();
+        let scratch = vec![zero; fft.scratch_len()];
+        Ok(MultiSpectralDriver {
+            fft,
+            pre_window,
+            post_window,
+            slide_size,
+            linking,
+            buffer: vec![T::zero(); window_size],
+            half_spectra: vec![vec![zero; half_len]; channels],
+            half_mix: vec![zero; half_len],
+            half_processed: vec![zero; half_len],
+            spectrum: vec![zero; window_size],
+            scratch,
+            processors,
+        })
+    }
+}
+
+impl<T: Float, P: SpectralProcessor<T>> MultiSpectralDriver<T, P> {
+    pub fn window_size(&self) -> usize {
+        self.pre_window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    pub fn channels(&self) -> usize {
+        self.half_spectra.len()
+    }
+
+    pub fn linking(&self) -> Linking {
+        self.linking
+    }
+
+    /// The processor of each channel, or the one shared processor if linked.
+    pub fn processors_mut(&mut self) -> &mut [P] {
+        &mut self.processors
+    }
+
+    pub fn reset(&mut self) {
+        for processor in &mut self.processors {
+            processor.reset();
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        self.window_size() - self.slide_size + self.processors[0].latency()
+    }
+
+    /// Process a window of each channel. All the slices must have the window size.
+    pub fn process<I: AsRef<[T]>, O: AsMut<[T]>>(&mut self, inputs: &[I], outputs: &mut [O]) {
+        assert_eq!(inputs.len(), self.channels());
+        assert_eq!(outputs.len(), self.channels());
+
+        for (input, half_spectrum) in inputs.iter().zip(&mut self.half_spectra) {
+            for ((b, &x), &w) in self
+                .buffer
+                .iter_mut()
+                .zip(input.as_ref())
+                .zip(&self.pre_window)
+            {
+                *b = x * w;
+            }
+            self.fft
+                .forward_real_with_scratch(&mut self.buffer, half_spectrum, &mut self.scratch);
+        }
+
+        match self.linking {
+            Linking::Independent => {
+                for (processor, half_spectrum) in
+                    self.processors.iter_mut().zip(&mut self.half_spectra)
+                {
+                    processor.process_half(half_spectrum, &mut self.spectrum);
+                }
+            }
+            Linking::Linked => self.process_linked(),
+        }
+
+        let scale = T::one() / T::from(self.buffer.len()).unwrap();
+        for (output, half_spectrum) in outputs.iter_mut().zip(&mut self.half_spectra) {
+            self.fft
+                .inverse_real_with_scratch(half_spectrum, &mut self.buffer, &mut self.scratch);
+            for ((y, &x), &w) in output
+                .as_mut()
+                .iter_mut()
+                .zip(&self.buffer)
+                .zip(&self.post_window)
+            {
+                *y = x * scale * w;
+            }
+        }
+    }
+
+    /// Same as `process()` but returns new `Vec`s, for `transform::transform_multi()`.
+    pub fn process_to_vec<I: AsRef<[T]>>(&mut self, inputs: &[I]) -> Vec<Vec<T>> {
+        let mut outputs = vec![vec![T::zero(); self.window_size()]; self.channels()];
+        self.process(inputs, &mut outputs);
+        outputs
+    }
+
+    fn process_linked(&mut self) {
+        // The mix has the phase of the sum and the sum of the magnitudes,
+        // so that channels in opposite phase do not cancel out.
+        for (i, x) in self.half_mix.iter_mut().enumerate() {
+            let (sum, norm) = self
+                .half_spectra
+                .iter()
+                .fold((Complex::from(T::zero()), T::zero()), |(s, n), h| {
+                    (s + h[i], n + h[i].norm())
+                });
+            let sum_norm = sum.norm();
+            *x = if T::zero() < sum_norm {
+                sum * (norm / sum_norm)
+            } else {
+                Complex::from(norm)
+            };
+        }
+
+        let processor = &mut self.processors[0];
+        let ratio = processor.frequency_ratio();
+        self.half_processed.copy_from_slice(&self.half_mix);
+        processor.process_half(&mut self.half_processed, &mut self.spectrum);
+
+        // Each output bin takes the level and the phase of the channel relative to the mix
+        // at the bin it came from.
+        let half_len = self.half_mix.len();
+        for half_spectrum in &mut self.half_spectra {
+            for (k, &y) in self.half_processed.iter().enumerate() {
+                let j = (T::from(k).unwrap() / ratio)
+                    .round()
+                    .to_usize()
+                    .unwrap_or(half_len);
+                self.spectrum[k] = match self.half_mix.get(j) {
+                    Some(&m) if T::zero() < m.norm() => y * half_spectrum[j] / m,
+                    _ => Complex::from(T::zero()),
+                };
+            }
+            half_spectrum.copy_from_slice(&self.spectrum[..half_len]);
+        }
+    }
+}
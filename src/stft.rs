@@ -0,0 +1,314 @@
+//! Short-time Fourier transform of whole signals, for offline spectral editing.
+//!
+//! ```
+//! # use voiche::{stft::{istft, stft}, windows::hann_window};
+//! let window = hann_window(1024);
+//! let input = vec![0.0f32; 4800];
+//!
+//! // Cut above bin 100.
+//! let mut spectrogram = stft(&window, 256, &input);
+//! for frame in 0..spectrogram.frames.len() {
+//!     for bin in 100..spectrogram.bins() {
+//!         spectrogram.set_magnitude(frame, bin, 0.0);
+//!     }
+//! }
+//! let output = istft(&spectrogram);
+//! assert_eq!(output.len(), input.len());
+//! ```
+
+use crate::{fft::Fft, num_complex::Complex, num_traits::Zero, Float};
+
+/// Spectra of overlapping windows of a signal, made by `stft()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrogram<T: Float> {
+    /// Half spectra of `window_size / 2 + 1` bins.
+    /// Frame `i` is of the window from `i * slide_size` of the signal padded with `window_size - slide_size` zeros,
+    /// so that every sample is in as many windows.
+    pub frames: Vec<Vec<Complex<T>>>,
+    window: Vec<T>,
+    slide_size: usize,
+    len: usize,
+}
+
+impl<T: Float> Spectrogram<T> {
+    /// Spectrogram of silence of `len` samples, to be filled in, e.g. by `set_magnitude()`.
+    pub fn new(window: Vec<T>, slide_size: usize, len: usize) -> Self {
+        assert!(0 < slide_size && slide_size <= window.len());
+        let frames = if len == 0 {
+            0
+        } else {
+            (len + window.len() - slide_size).div_ceil(slide_size)
+        };
+        Spectrogram {
+            frames: vec![vec![Complex::zero(); window.len() / 2 + 1]; frames],
+            window,
+            slide_size,
+            len,
+        }
+    }
+
+    pub fn window(&self) -> &[T] {
+        &self.window
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    /// Number of samples of the signal.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bins of each frame, `window_size / 2 + 1`.
+    pub fn bins(&self) -> usize {
+        self.window.len() / 2 + 1
+    }
+
+    /// Center frequency of `bin` in Hz.
+    pub fn frequency(&self, bin: usize, sample_rate: T) -> T {
+        T::from(bin).unwrap() * sample_rate / T::from(self.window.len()).unwrap()
+    }
+
+    pub fn magnitude(&self, frame: usize, bin: usize) -> T {
+        self.frames[frame][bin].norm()
+    }
+
+    /// Phase in radians.
+    pub fn phase(&self, frame: usize, bin: usize) -> T {
+        self.frames[frame][bin].arg()
+    }
+
+    /// Set the magnitude keeping the phase.
+    pub fn set_magnitude(&mut self, frame: usize, bin: usize, magnitude: T) {
+        let phase = self.phase(frame, bin);
+        self.frames[frame][bin] = Complex::from_polar(magnitude, phase);
+    }
+
+    /// Set the phase keeping the magnitude.
+    pub fn set_phase(&mut self, frame: usize, bin: usize, phase: T) {
+        let magnitude = self.magnitude(frame, bin);
+        self.frames[frame][bin] = Complex::from_polar(magnitude, phase);
+    }
+
+    /// Magnitudes of all frames, e.g. for `phase_reconstruction::Rtisi`.
+    pub fn magnitudes(&self) -> Vec<Vec<T>> {
+        self.frames
+            .iter()
+            .map(|frame| frame.iter().map(|x| x.norm()).collect())
+            .collect()
+    }
+
+    /// Set the magnitudes of all frames keeping the phases.
+    pub fn set_magnitudes(&mut self, magnitudes: &[Vec<T>]) {
+        assert_eq!(magnitudes.len(), self.frames.len());
+        for (frame, magnitude) in self.frames.iter_mut().zip(magnitudes) {
+            for (x, &m) in frame.iter_mut().zip(magnitude) {
+                *x = Complex::from_polar(m, x.arg());
+            }
+        }
+    }
+
+    pub fn phases(&self) -> Vec<Vec<T>> {
+        self.frames
+            .iter()
+            .map(|frame| frame.iter().map(|x| x.arg()).collect())
+            .collect()
+    }
+}
+
+/// Short-time Fourier transform of `buf` with `window` sliding by `slide_size`.
+pub fn stft<T: Float>(window: &[T], slide_size: usize, buf: &[T]) -> Spectrogram<T> {
+    let mut spectrogram = Spectrogram::new(window.to_vec(), slide_size, buf.len());
+    if buf.is_empty() {
+        return spectrogram;
+    }
+    let mut stft = Stft::new(window, slide_size);
+    let mut padded = vec![T::zero(); stft.padding()];
+    padded.extend_from_slice(buf);
+    padded.resize(stft.signal_len(spectrogram.frames.len()), T::zero());
+    for (i, frame) in spectrogram.frames.iter_mut().enumerate() {
+        stft.analyze(&padded[i * slide_size..], frame);
+    }
+    spectrogram
+}
+
+/// Inverse of `stft()`, which is exact for an unmodified spectrogram.
+///
+/// The overlapping windows are added and divided by the sum of the squared windows at each sample,
+/// so an edited spectrogram becomes the signal whose spectrogram is the closest to it in the least squares sense.
+pub fn istft<T: Float>(spectrogram: &Spectrogram<T>) -> Vec<T> {
+    if spectrogram.frames.is_empty() {
+        return vec![];
+    }
+    let mut stft = Stft::new(&spectrogram.window, spectrogram.slide_size);
+    let weight = stft.weight(spectrogram.frames.len());
+    let mut signal = vec![T::zero(); weight.len()];
+    stft.overlap_add(&spectrogram.frames, &weight, &mut signal);
+    signal.drain(..stft.padding());
+    signal.truncate(spectrogram.len);
+    signal
+}
+
+/// Buffers to transform frames of a signal without padding, from the start of frame 0.
+pub(crate) struct Stft<T: Float> {
+    pub(crate) window: Vec<T>,
+    pub(crate) slide_size: usize,
+    pub(crate) fft: Fft<T>,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+}
+
+impl<T: Float> Stft<T> {
+    pub(crate) fn new(window: &[T], slide_size: usize) -> Self {
+        assert!(0 < slide_size && slide_size <= window.len());
+        let fft = Fft::new(window.len());
+        Stft {
+            window: window.to_vec(),
+            slide_size,
+            buffer: vec![T::zero(); window.len()],
+            half_spectrum: vec![Complex::zero(); fft.half_len()],
+            fft,
+        }
+    }
+
+    /// Zeros before the signal in `stft()`.
+    fn padding(&self) -> usize {
+        self.window.len() - self.slide_size
+    }
+
+    /// Number of samples covered by `frames` frames.
+    pub(crate) fn signal_len(&self, frames: usize) -> usize {
+        (frames - 1) * self.slide_size + self.window.len()
+    }
+
+    /// Spectrum of the window from the start of `signal`.
+    pub(crate) fn analyze(&mut self, signal: &[T], spectrum: &mut [Complex<T>]) {
+        analyze(&self.fft, &self.window, signal, &mut self.buffer, spectrum);
+    }
+
+    /// The inverse transform of `spectrum` multiplied by the window.
+    pub(crate) fn synthesize(&mut self, spectrum: &[Complex<T>]) -> &[T] {
+        self.half_spectrum.copy_from_slice(spectrum);
+        synthesize(
+            &self.fft,
+            &self.window,
+            &mut self.half_spectrum,
+            &mut self.buffer,
+        );
+        &self.buffer
+    }
+
+    /// Sum of the squared windows of `frames` frames at each sample.
+    pub(crate) fn weight(&self, frames: usize) -> Vec<T> {
+        let mut weight = vec![T::zero(); self.signal_len(frames)];
+        for i in 0..frames {
+            for (y, &w) in weight[i * self.slide_size..].iter_mut().zip(&self.window) {
+                *y = *y + w * w;
+            }
+        }
+        weight
+    }
+
+    /// Overlap-add the synthesized frames and normalize them by `weight`.
+    pub(crate) fn overlap_add(
+        &mut self,
+        frames: &[Vec<Complex<T>>],
+        weight: &[T],
+        signal: &mut [T],
+    ) {
+        signal.fill(T::zero());
+        for (i, spectrum) in frames.iter().enumerate() {
+            let offset = i * self.slide_size;
+            let synthesized = self.synthesize(spectrum);
+            for (y, &x) in signal[offset..].iter_mut().zip(synthesized) {
+                *y = *y + x;
+            }
+        }
+        for (y, &w) in signal.iter_mut().zip(weight) {
+            *y = normalize(*y, w);
+        }
+    }
+}
+
+/// The `len / 2 + 1` bins of the spectrum of `signal` multiplied by `window`.
+/// `buffer` is a work area of the window size.
+pub(crate) fn analyze<T: Float>(
+    fft: &Fft<T>,
+    window: &[T],
+    signal: &[T],
+    buffer: &mut [T],
+    half_spectrum: &mut [Complex<T>],
+) {
+    for ((b, &x), &w) in buffer.iter_mut().zip(signal).zip(window) {
+        *b = x * w;
+    }
+    fft.forward_real(buffer, half_spectrum);
+}
+
+/// The inverse transform of `half_spectrum` multiplied by `window`, into `buffer`.
+/// `half_spectrum` is used as a work area.
+pub(crate) fn synthesize<T: Float>(
+    fft: &Fft<T>,
+    window: &[T],
+    half_spectrum: &mut [Complex<T>],
+    buffer: &mut [T],
+) {
+    fft.inverse_real(half_spectrum, buffer);
+    let scale = T::one() / T::from(window.len()).unwrap();
+    for (b, &w) in buffer.iter_mut().zip(window) {
+        *b = *b * scale * w;
+    }
+}
+
+/// `sum` divided by the sum of the squared windows `weight`, or 0 where no window reaches.
+pub(crate) fn normalize<T: Float>(sum: T, weight: T) -> T {
+    if weight <= T::epsilon() {
+        T::zero()
+    } else {
+        sum / weight
+    }
+}
+
+#[test]
+fn test() {
+    let buf = crate::test_util::noise(3000, 1);
+    let close = |a: &[f64], b: &[f64]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9)
+    };
+
+    // Exact with and without COLA of the squared window, to the edges.
+    for (window, slide_size) in [
+        (crate::windows::hann_window(256), 64),
+        (crate::windows::hann_window(256), 128),
+        (crate::windows::hamming_window(256), 100),
+        (vec![1.0; 100], 100),
+    ] {
+        let spectrogram = stft(&window, slide_size, &buf);
+        assert_eq!(spectrogram.bins(), window.len() / 2 + 1);
+        assert!(close(&istft(&spectrogram), &buf));
+    }
+
+    // Editing
+    let window = crate::windows::hann_window(256);
+    let mut spectrogram = stft(&window, 64, &buf);
+    let (magnitudes, phases) = (spectrogram.magnitudes(), spectrogram.phases());
+    for frame in 0..spectrogram.frames.len() {
+        for bin in 0..spectrogram.bins() {
+            spectrogram.set_magnitude(frame, bin, magnitudes[frame][bin] * 0.5);
+            assert!((spectrogram.phase(frame, bin) - phases[frame][bin]).abs() < 1e-9);
+        }
+    }
+    let halved: Vec<f64> = buf.iter().map(|x| x * 0.5).collect();
+    assert!(close(&istft(&spectrogram), &halved));
+
+    assert!(istft(&stft(&window, 64, &[])).is_empty());
+}
@@ -0,0 +1,40 @@
+//! Signals shared by the unit tests, like `tests/common`.
+
+use crate::{fft::Fft, pitch_detection::pitch_detect, windows};
+
+/// Uniform noise in -0.5..0.5 from a linear congruential generator.
+pub(crate) fn noise(len: usize, seed: u32) -> Vec<f64> {
+    let mut seed = seed;
+    (0..len)
+        .map(|_| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            seed as f64 / u32::MAX as f64 - 0.5
+        })
+        .collect()
+}
+
+pub(crate) const SAMPLE_RATE: u32 = 16000;
+
+/// A tone of `f0` Hz with 5 harmonics at `SAMPLE_RATE`.
+pub(crate) fn tone(f0: f64, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE as f64;
+            (1..=5)
+                .map(|k| (std::f64::consts::TAU * f0 * k as f64 * t).sin() / k as f64)
+                .sum::<f64>()
+                * 0.2
+        })
+        .collect()
+}
+
+/// Frequency in Hz found by `pitch_detect()` in the window of 1024 samples at `time` seconds.
+pub(crate) fn detect(buf: &[f64], time: f64) -> f64 {
+    let window_size = 1024;
+    let fft = Fft::new(window_size);
+    let window = windows::hann_window(window_size);
+    let start = (time * SAMPLE_RATE as f64) as usize;
+    let (wavelength, _) =
+        pitch_detect(&fft, &window, &buf[start..][..window_size], 20.0, 0.5).unwrap();
+    SAMPLE_RATE as f64 / wavelength
+}
@@ -0,0 +1,361 @@
+use std::{cmp::Ordering, f64::consts::TAU};
+
+use crate::{
+    fft::Fft,
+    num_complex::Complex,
+    num_traits::Zero,
+    pitch_shift::wrap_phase,
+    transform::buffer_overlapping_write,
+    windows::{self, WindowError},
+    Float,
+};
+
+/// Phase vocoder which changes the duration of a signal without changing its pitch.
+///
+/// Windows are taken from the input every `slide_size / time_rate` samples
+/// and written to the output every `slide_size` samples,
+/// so the output is `time_rate` times as long as the input.
+/// `time_rate` must be positive, and can be changed between calls to `process()`.
+/// The phases are locked around spectral peaks to keep the sound from getting phasey.
+///
+/// It is used like `transform::Transformer`.
+///
+/// # Example
+/// ```no_run
+/// # use voiche::{time_stretch::TimeStretcher, windows};
+/// let mut time_stretcher = TimeStretcher::new(
+///     windows::hann_window(1024),
+///     windows::hann_window(1024),
+///     256,
+///     1.5,
+/// );
+///
+/// loop {
+///     // read input
+///     let input = vec![0.0f32; 256];
+///
+///     time_stretcher.input_slice(&input);
+///     time_stretcher.process();
+///
+///     let mut output = vec![0.0; 256];
+///     while time_stretcher.output_slice_exact(&mut output) {
+///         // write output
+///         todo!();
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct TimeStretcher<T: Float> {
+    pub time_rate: T,
+    fft: Fft<T>,
+    pre_window: Vec<T>,
+    post_window: Vec<T>,
+    slide_size: usize,
+    input_position: T,
+    input_slide_size: usize,
+    input_buffer: Vec<T>,
+    output_buffer: Vec<T>,
+    prev_input_phases: Vec<T>,
+    prev_output_phases: Vec<T>,
+    freqs: Vec<T>,
+    norms: Vec<T>,
+    peaks: Vec<usize>,
+    first: bool,
+    buffer: Vec<T>,
+    half_spectrum: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: Float> TimeStretcher<T> {
+    /// Panics if the windows can't reconstruct a signal, see `try_new()`,
+    /// or if `time_rate` is not positive.
+    pub fn new(pre_window: Vec<T>, post_window: Vec<T>, slide_size: usize, time_rate: T) -> Self {
+        assert!(T::zero() < time_rate, "time_rate must be positive");
+        match Self::try_new(pre_window, post_window, slide_size, time_rate) {
+            Ok(x) => x,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Same as `new()` but returns an error if the windows can't reconstruct a signal,
+    /// see `windows::check_reconstruction()`.
+    /// `time_rate` is checked by `process()`.
+    pub fn try_new(
+        pre_window: Vec<T>,
+        post_window: Vec<T>,
+        slide_size: usize,
+        time_rate: T,
+    ) -> Result<Self, WindowError> {
+        let post_window = windows::check_reconstruction(&pre_window, &post_window, slide_size)?
+            .normalize(&post_window);
+
+        let window_size = pre_window.len();
+        let fft = Fft::new(window_size);
+        let half_len = fft.half_len();
+        let scratch = vec![Complex::zero(); fft.scratch_len()];
+        Ok(TimeStretcher {
+            time_rate,
+            fft,
+            pre_window,
+            post_window,
+            slide_size,
+            input_position: T::zero(),
+            input_slide_size: 0,
+            input_buffer: vec![T::zero(); window_size - slide_size],
+            output_buffer: vec![],
+            prev_input_phases: vec![T::zero(); half_len],
+            prev_output_phases: vec![T::zero(); half_len],
+            freqs: vec![T::zero(); half_len],
+            norms: vec![T::zero(); half_len],
+            peaks: Vec::with_capacity(half_len),
+            first: true,
+            buffer: vec![T::zero(); window_size],
+            half_spectrum: vec![Complex::zero(); half_len],
+            scratch,
+        })
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.pre_window.len()
+    }
+
+    pub fn slide_size(&self) -> usize {
+        self.slide_size
+    }
+
+    /// Clear the buffered signal and the phases.
+    pub fn reset(&mut self) {
+        let overlap_size = self.window_size() - self.slide_size;
+        self.input_buffer.clear();
+        self.input_buffer.resize(overlap_size, T::zero());
+        self.output_buffer.clear();
+        self.input_position = T::zero();
+        self.input_slide_size = 0;
+        self.first = true;
+    }
+
+    pub fn input_slice(&mut self, slice: &[T]) {
+        self.input_buffer.extend_from_slice(slice);
+    }
+
+    pub fn output_slice_exact(&mut self, slice: &mut [T]) -> bool {
+        let overlap_size = self.window_size() - self.slide_size;
+        if self.output_buffer.len() >= slice.len() + overlap_size {
+            slice.copy_from_slice(&self.output_buffer[..slice.len()]);
+            self.output_buffer.drain(0..slice.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Flush the remaining input and append all the remaining output to `vec`.
+    pub fn finish(mut self, vec: &mut Vec<T>) {
+        let len = self.input_buffer.len();
+        self.input_buffer
+            .resize(len + self.window_size(), T::zero());
+        self.process();
+        vec.extend_from_slice(&self.output_buffer);
+    }
+
+    pub fn process(&mut self) {
+        assert!(T::zero() < self.time_rate, "time_rate must be positive");
+        let window_size = self.window_size();
+
+        loop {
+            // The input position advances by a fraction, so the slide size varies a little.
+            let input_slide_size = self.input_position.floor().to_usize().unwrap();
+            if self.input_buffer.len() < input_slide_size + window_size {
+                break;
+            }
+            self.input_buffer.drain(0..input_slide_size);
+            self.input_position = self.input_position - T::from(input_slide_size).unwrap();
+            self.input_slide_size = input_slide_size;
+
+            self.process_window();
+            buffer_overlapping_write(self.slide_size, &mut self.output_buffer, &self.buffer);
+
+            self.input_position =
+                self.input_position + T::from(self.slide_size).unwrap() / self.time_rate;
+        }
+    }
+
+    fn process_window(&mut self) {
+        let len = self.window_size();
+
+        for ((b, &x), &w) in self
+            .buffer
+            .iter_mut()
+            .zip(&self.input_buffer)
+            .zip(&self.pre_window)
+        {
+            *b = x * w;
+        }
+        self.fft.forward_real_with_scratch(
+            &mut self.buffer,
+            &mut self.half_spectrum,
+            &mut self.scratch,
+        );
+
+        let input_slide_size = T::from(self.input_slide_size).unwrap();
+        for i in 0..self.half_spectrum.len() {
+            let (norm, phase) = self.half_spectrum[i].to_polar();
+            let bin_center_freq = T::from(TAU * i as f64 / len as f64).unwrap();
+
+            // The frequencies are kept if the window did not move.
+            if 0 < self.input_slide_size {
+                let phase_diff = wrap_phase(
+                    phase - self.prev_input_phases[i] - bin_center_freq * input_slide_size,
+                );
+                self.freqs[i] = bin_center_freq + phase_diff / input_slide_size;
+            }
+            self.norms[i] = norm;
+            self.prev_input_phases[i] = phase;
+        }
+
+        if self.first {
+            self.prev_output_phases
+                .copy_from_slice(&self.prev_input_phases);
+            self.first = false;
+        } else {
+            self.lock_phases();
+        }
+
+        for ((x, &norm), &phase) in self
+            .half_spectrum
+            .iter_mut()
+            .zip(&self.norms)
+            .zip(&self.prev_output_phases)
+        {
+            *x = Complex::from_polar(norm, phase);
+        }
+
+        self.fft.inverse_real_with_scratch(
+            &mut self.half_spectrum,
+            &mut self.buffer,
+            &mut self.scratch,
+        );
+        let scale = T::one() / T::from(len).unwrap();
+        for (x, &w) in self.buffer.iter_mut().zip(&self.post_window) {
+            *x = *x * scale * w;
+        }
+    }
+
+    /// Identity phase locking (Laroche & Dolson).
+    /// Only the peaks advance their phases by their frequencies,
+    /// and the other bins keep their analysis phases relative to the peak of their region.
+    fn lock_phases(&mut self) {
+        let half = self.norms.len();
+        let slide_size = T::from(self.slide_size).unwrap();
+        let norms = &self.norms;
+
+        self.peaks.clear();
+        for i in 0..half {
+            let left = i == 0 || norms[i - 1] < norms[i];
+            let right = i + 1 == half || norms[i + 1] <= norms[i];
+            if T::zero() < norms[i] && left && right {
+                self.peaks.push(i);
+            }
+        }
+
+        if self.peaks.is_empty() {
+            for (phase, &freq) in self.prev_output_phases.iter_mut().zip(&self.freqs) {
+                *phase = wrap_phase(*phase + freq * slide_size);
+            }
+            return;
+        }
+
+        let mut start = 0;
+        for (j, &peak) in self.peaks.iter().enumerate() {
+            // The region of a peak ends at the lowest bin before the next peak.
+            let end = if let Some(&next) = self.peaks.get(j + 1) {
+                (peak..next)
+                    .min_by(|&a, &b| norms[a].partial_cmp(&norms[b]).unwrap_or(Ordering::Equal))
+                    .unwrap()
+                    + 1
+            } else {
+                half
+            };
+
+            let peak_phase =
+                wrap_phase(self.prev_output_phases[peak] + self.freqs[peak] * slide_size);
+            let rotation = peak_phase - self.prev_input_phases[peak];
+            for k in start..end {
+                self.prev_output_phases[k] = wrap_phase(self.prev_input_phases[k] + rotation);
+            }
+            start = end;
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let freq = 0.05;
+    let buf: Vec<f64> = (0..16000).map(|i| (i as f64 * freq).sin()).collect();
+
+    for time_rate in [0.75, 1.0, 1.5] {
+        let output = crate::api::time_stretch(
+            crate::windows::hann_window(window_size),
+            crate::windows::hann_window(window_size),
+            slide_size,
+            time_rate,
+            &buf,
+        );
+        assert_eq!(
+            output.len(),
+            (buf.len() as f64 * time_rate).round() as usize
+        );
+
+        // The amplitude and the frequency are kept.
+        let middle = &output[window_size * 2..output.len() - window_size * 2];
+        let rms = (middle.iter().map(|x| x * x).sum::<f64>() / middle.len() as f64).sqrt();
+        assert!((rms - 0.5f64.sqrt()).abs() < 0.05, "{}", rms);
+        let crossings = middle
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && 0.0 <= w[1])
+            .count();
+        let expected = middle.len() as f64 * freq / TAU;
+        assert!((crossings as f64 - expected).abs() < 2.0);
+    }
+
+    // Streaming gives the same output as offline processing.
+    let mut expected = Vec::new();
+    let time_stretcher = TimeStretcher::new(
+        crate::windows::hann_window(window_size),
+        crate::windows::hann_window(window_size),
+        slide_size,
+        1.5,
+    );
+    let mut streaming = time_stretcher.clone();
+    let mut offline = time_stretcher;
+    offline.input_slice(&buf);
+    offline.finish(&mut expected);
+
+    let mut actual = Vec::new();
+    let mut output = [0.0; 100];
+    for chunk in buf.chunks(300) {
+        streaming.input_slice(chunk);
+        streaming.process();
+        while streaming.output_slice_exact(&mut output) {
+            actual.extend_from_slice(&output);
+        }
+    }
+    streaming.finish(&mut actual);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+#[should_panic(expected = "time_rate must be positive")]
+fn test_zero_time_rate() {
+    let mut time_stretcher = TimeStretcher::new(
+        crate::windows::hann_window(64),
+        crate::windows::hann_window(64),
+        16,
+        1.0,
+    );
+    time_stretcher.time_rate = 0.0;
+    time_stretcher.input_slice(&[0.0; 128]);
+    time_stretcher.process();
+}
@@ -1,16 +1,21 @@
 use std::iter::Sum;
 
-use crate::Float;
+use crate::{ring_buffer::RingBuffer, Float};
 
 /// Convert a buffer to another buffer by applying a function to each window.
 ///
 /// You can also use overlapping_flatten() like this:
 /// ```
-/// buf.windows(window_size)
+/// # use voiche::overlapping_flatten::OverlappingFlattenTrait;
+/// # let (window_size, slide_size) = (1024, 256);
+/// # let buf = vec![0.0f32; 4096];
+/// # let process = |b: &[f32]| b.to_vec();
+/// let output = buf
+///     .windows(window_size)
 ///     .step_by(slide_size)
 ///     .map(|b| process(&b))
 ///     .overlapping_flatten(window_size - slide_size)
-///     .collect::<Vec<_>>()
+///     .collect::<Vec<_>>();
 /// ```
 pub fn transform<T: Float + Sum>(
     window_size: usize,
@@ -34,11 +39,91 @@ pub fn transform<T: Float + Sum>(
     output
 }
 
+/// Two-input version of `transform()`, e.g. for `cross_synthesis::CrossSynthesis`.
+///
+/// `process` gets windows of `first` and `second` at the same position.
+/// The shorter buffer is padded with zeros.
+pub fn transform2<T: Float>(
+    window_size: usize,
+    slide_size: usize,
+    mut process: impl FnMut(&[T], &[T]) -> Vec<T>,
+    first: &[T],
+    second: &[T],
+) -> Vec<T> {
+    let len = first.len().max(second.len());
+    if len == 0 {
+        return vec![];
+    }
+
+    let mut output = Vec::with_capacity(len);
+    let window = |buffer: &[T], i: usize| {
+        let mut buf = buffer[i.min(buffer.len())..(i + window_size).min(buffer.len())].to_vec();
+        buf.resize(window_size, T::zero());
+        buf
+    };
+    for i in 0..(len - 1) / slide_size + 1 {
+        let i = i * slide_size;
+        let buf = process(&window(first, i), &window(second, i));
+        buffer_overlapping_write(slide_size, &mut output, &buf);
+    }
+    output
+}
+
+/// Multichannel version of `transform()`.
+///
+/// `buffers` are planar, one `Vec` per channel of the same length,
+/// and `process` gets a window of each channel and returns a window of each output channel.
+pub fn transform_multi<T: Float>(
+    window_size: usize,
+    slide_size: usize,
+    mut process: impl FnMut(&[Vec<T>]) -> Vec<Vec<T>>,
+    buffers: &[Vec<T>],
+) -> Vec<Vec<T>> {
+    let len = buffers.first().map_or(0, |b| b.len());
+    assert!(buffers.iter().all(|b| b.len() == len));
+    if len == 0 {
+        return vec![vec![]; buffers.len()];
+    }
+
+    let mut outputs: Vec<Vec<T>> = Vec::new();
+    let mut windows = vec![vec![T::zero(); window_size]; buffers.len()];
+    for i in 0..(len - 1) / slide_size + 1 {
+        let i = i * slide_size;
+        for (window, buffer) in windows.iter_mut().zip(buffers) {
+            let slice = &buffer[i..(i + window_size).min(len)];
+            window[..slice.len()].copy_from_slice(slice);
+            window[slice.len()..].fill(T::zero());
+        }
+        let processed = process(&windows);
+        outputs.resize_with(processed.len(), || Vec::with_capacity(len));
+        for (output, buf) in outputs.iter_mut().zip(&processed) {
+            buffer_overlapping_write(slide_size, output, buf);
+        }
+    }
+    outputs
+}
+
+/// Split interleaved samples into `channels` planar buffers.
+pub fn deinterleave<T: Float>(buffer: &[T], channels: usize) -> Vec<Vec<T>> {
+    (0..channels)
+        .map(|c| buffer.iter().skip(c).step_by(channels).copied().collect())
+        .collect()
+}
+
+/// Interleave planar buffers of the same length.
+pub fn interleave<T: Float>(buffers: &[Vec<T>]) -> Vec<T> {
+    let len = buffers.first().map_or(0, |b| b.len());
+    (0..len)
+        .flat_map(|i| buffers.iter().map(move |b| b[i]))
+        .collect()
+}
+
 /// A structure for real-time signal transformation.
 ///
 /// # Example
-/// ```
-/// let mut transformer = Transformer::new(1024, 256, |buf| buf.to_vec());
+/// ```no_run
+/// # use voiche::transform::Transformer;
+/// let mut transformer = Transformer::new(1024, 256, |buf: &[f32]| buf.to_vec());
 ///
 /// loop {
 ///     // read input
@@ -116,18 +201,365 @@ impl<T: Float, F: FnMut(&[T]) -> Vec<T>> Transformer<T, F> {
     }
 }
 
+/// Two-input version of `Transformer`, e.g. for `cross_synthesis::CrossSynthesis`.
+pub struct Transformer2<T: Float, F: FnMut(&[T], &[T]) -> Vec<T>> {
+    window_size: usize,
+    input_overlap_size: usize,
+    input_buffers: [Vec<T>; 2],
+    output_buffer: Vec<T>,
+    process_fn: F,
+}
+
+impl<T: Float, F: FnMut(&[T], &[T]) -> Vec<T>> Transformer2<T, F> {
+    pub fn new(window_size: usize, slide_size: usize, process_fn: F) -> Self {
+        let input_overlap_size = window_size - slide_size;
+        Transformer2 {
+            window_size,
+            input_overlap_size,
+            input_buffers: [
+                vec![T::zero(); input_overlap_size],
+                vec![T::zero(); input_overlap_size],
+            ],
+            output_buffer: vec![],
+            process_fn,
+        }
+    }
+
+    /// The inputs are processed as far as both have come.
+    pub fn input_slices(&mut self, first: &[T], second: &[T]) {
+        self.input_buffers[0].extend_from_slice(first);
+        self.input_buffers[1].extend_from_slice(second);
+    }
+
+    pub fn output_slice_exact(&mut self, slice: &mut [T]) -> bool {
+        if self.output_buffer.len() >= slice.len() + self.input_overlap_size {
+            slice.copy_from_slice(&self.output_buffer[..slice.len()]);
+            self.output_buffer.drain(0..slice.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Flush the remaining input, padding the shorter input with zeros, and append all the remaining output to `vec`.
+    pub fn finish(mut self, vec: &mut Vec<T>) {
+        let len = self.input_buffers[0].len().max(self.input_buffers[1].len()) + self.window_size
+            - self.input_overlap_size;
+        for buffer in &mut self.input_buffers {
+            buffer.resize(len, T::zero());
+        }
+        self.process();
+        vec.extend_from_slice(&self.output_buffer);
+    }
+
+    pub fn process(&mut self) {
+        let slide_size = self.window_size - self.input_overlap_size;
+        let [first, second] = &mut self.input_buffers;
+
+        while first.len().min(second.len()) >= self.window_size {
+            let buf = (self.process_fn)(&first[..self.window_size], &second[..self.window_size]);
+
+            buffer_overlapping_write(slide_size, &mut self.output_buffer, &buf);
+
+            first.drain(0..slide_size);
+            second.drain(0..slide_size);
+        }
+    }
+}
+
+/// Multichannel version of `Transformer`.
+///
+/// The input and the output are given either interleaved or planar.
+/// `process_fn` gets a window of each channel and returns a window of each channel.
+pub struct MultiTransformer<T: Float, F: FnMut(&[Vec<T>]) -> Vec<Vec<T>>> {
+    window_size: usize,
+    input_overlap_size: usize,
+    input_buffers: Vec<Vec<T>>,
+    output_buffers: Vec<Vec<T>>,
+    windows: Vec<Vec<T>>,
+    process_fn: F,
+}
+
+impl<T: Float, F: FnMut(&[Vec<T>]) -> Vec<Vec<T>>> MultiTransformer<T, F> {
+    pub fn new(window_size: usize, slide_size: usize, channels: usize, process_fn: F) -> Self {
+        let input_overlap_size = window_size - slide_size;
+        MultiTransformer {
+            window_size,
+            input_overlap_size,
+            input_buffers: vec![vec![T::zero(); input_overlap_size]; channels],
+            output_buffers: vec![vec![]; channels],
+            windows: vec![vec![T::zero(); window_size]; channels],
+            process_fn,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.input_buffers.len()
+    }
+
+    /// `slices` has a slice for each channel.
+    pub fn input_planar<S: AsRef<[T]>>(&mut self, slices: &[S]) {
+        assert_eq!(slices.len(), self.channels());
+        for (buffer, slice) in self.input_buffers.iter_mut().zip(slices) {
+            buffer.extend_from_slice(slice.as_ref());
+        }
+    }
+
+    /// `slice` has whole frames of a sample for each channel.
+    pub fn input_interleaved(&mut self, slice: &[T]) {
+        let channels = self.channels();
+        assert!(
+            slice.len().is_multiple_of(channels),
+            "the length must be a multiple of the channels"
+        );
+        for (c, buffer) in self.input_buffers.iter_mut().enumerate() {
+            buffer.extend(slice.iter().skip(c).step_by(channels));
+        }
+    }
+
+    /// Fill each of `slices` if enough output is ready.
+    /// The slices must have the same length.
+    pub fn output_planar_exact<S: AsMut<[T]>>(&mut self, slices: &mut [S]) -> bool {
+        assert_eq!(slices.len(), self.channels());
+        let len = slices.first_mut().map_or(0, |s| s.as_mut().len());
+        if self.output_buffers[0].len() < len + self.input_overlap_size {
+            return false;
+        }
+        for (buffer, slice) in self.output_buffers.iter_mut().zip(slices) {
+            slice.as_mut().copy_from_slice(&buffer[..len]);
+            buffer.drain(0..len);
+        }
+        true
+    }
+
+    /// Fill `slice` with interleaved samples if enough output is ready.
+    pub fn output_interleaved_exact(&mut self, slice: &mut [T]) -> bool {
+        let channels = self.channels();
+        assert!(
+            slice.len().is_multiple_of(channels),
+            "the length must be a multiple of the channels"
+        );
+        let len = slice.len() / channels;
+        if self.output_buffers[0].len() < len + self.input_overlap_size {
+            return false;
+        }
+        for (c, buffer) in self.output_buffers.iter_mut().enumerate() {
+            for (y, &x) in slice
+                .iter_mut()
+                .skip(c)
+                .step_by(channels)
+                .zip(&buffer[..len])
+            {
+                *y = x;
+            }
+            buffer.drain(0..len);
+        }
+        true
+    }
+
+    /// Flush the remaining input and append all the remaining output of each channel to `vecs`.
+    pub fn finish(mut self, vecs: &mut [Vec<T>]) {
+        let slide_size = self.window_size - self.input_overlap_size;
+        for buffer in &mut self.input_buffers {
+            buffer.resize(buffer.len() + slide_size, T::zero());
+        }
+        self.process();
+        for (vec, buffer) in vecs.iter_mut().zip(&self.output_buffers) {
+            vec.extend_from_slice(buffer);
+        }
+    }
+
+    pub fn process(&mut self) {
+        let slide_size = self.window_size - self.input_overlap_size;
+
+        while self.input_buffers[0].len() >= self.window_size {
+            for (window, buffer) in self.windows.iter_mut().zip(&self.input_buffers) {
+                window.copy_from_slice(&buffer[..self.window_size]);
+            }
+            let processed = (self.process_fn)(&self.windows);
+            for (output, buf) in self.output_buffers.iter_mut().zip(&processed) {
+                buffer_overlapping_write(slide_size, output, buf);
+            }
+            for buffer in &mut self.input_buffers {
+                buffer.drain(0..slide_size);
+            }
+        }
+    }
+}
+
+/// A real-time transformer that does no heap allocation after construction.
+///
+/// The input and output are kept in ring buffers sized for `block_size`,
+/// the largest slice passed to `input_slice()` / `output_slice_exact()` between
+/// calls to `process()`. `process_fn` writes its result into the given buffer
+/// instead of returning a new `Vec`.
+///
+/// # Example
+/// ```no_run
+/// # use voiche::transform::RingTransformer;
+/// let mut transformer = RingTransformer::new(1024, 256, 256, |input: &[f32], output: &mut [f32]| {
+///     output.copy_from_slice(input)
+/// });
+///
+/// loop {
+///     // read input
+///     let input = [0.0; 256];
+///
+///     transformer.input_slice(&input);
+///     transformer.process();
+///
+///     let mut output = [0.0; 256];
+///     while transformer.output_slice_exact(&mut output) {
+///        // write output
+///        todo!();
+///    }
+/// }
+/// ```
+pub struct RingTransformer<T: Float, F: FnMut(&[T], &mut [T])> {
+    window_size: usize,
+    slide_size: usize,
+    input_buffer: RingBuffer<T>,
+    output_buffer: RingBuffer<T>,
+    window_buffer: Vec<T>,
+    process_buffer: Vec<T>,
+    process_fn: F,
+}
+
+impl<T: Float, F: FnMut(&[T], &mut [T])> RingTransformer<T, F> {
+    pub fn new(window_size: usize, slide_size: usize, block_size: usize, process_fn: F) -> Self {
+        assert!(0 < slide_size && slide_size <= window_size);
+
+        let mut transformer = RingTransformer {
+            window_size,
+            slide_size,
+            input_buffer: RingBuffer::new(window_size + block_size),
+            output_buffer: RingBuffer::new(window_size + block_size * 2),
+            window_buffer: vec![T::zero(); window_size],
+            process_buffer: vec![T::zero(); window_size],
+            process_fn,
+        };
+        transformer.reset();
+        transformer
+    }
+
+    /// Clear buffered samples, as if the transformer was just created.
+    pub fn reset(&mut self) {
+        let overlap_size = self.window_size - self.slide_size;
+        self.input_buffer.clear();
+        self.input_buffer.push_zeros(overlap_size);
+        self.output_buffer.clear();
+        self.output_buffer.push_zeros(overlap_size);
+    }
+
+    pub fn process_fn_mut(&mut self) -> &mut F {
+        &mut self.process_fn
+    }
+
+    pub fn input_slice(&mut self, slice: &[T]) {
+        self.input_buffer.push_slice(slice);
+    }
+
+    pub fn output_slice_exact(&mut self, slice: &mut [T]) -> bool {
+        let overlap_size = self.window_size - self.slide_size;
+        if self.output_buffer.len() >= slice.len() + overlap_size {
+            self.output_buffer.pop_slice(slice);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn finish(mut self, vec: &mut Vec<T>) {
+        let overlap_size = self.window_size - self.slide_size;
+        let mut rest = self.slide_size;
+        while 0 < rest {
+            let size = rest.min(self.input_buffer.capacity() - self.input_buffer.len());
+            self.input_buffer.push_zeros(size);
+            rest -= size;
+            self.process();
+            self.drain_output(self.output_buffer.len() - overlap_size, vec);
+        }
+        self.drain_output(self.output_buffer.len(), vec);
+    }
+
+    fn drain_output(&mut self, size: usize, vec: &mut Vec<T>) {
+        let start = vec.len();
+        vec.resize(start + size, T::zero());
+        self.output_buffer.pop_slice(&mut vec[start..]);
+    }
+
+    pub fn process(&mut self) {
+        while self.next_window() {
+            (self.process_fn)(&self.window_buffer, &mut self.process_buffer);
+            self.push_window();
+        }
+    }
+
+    /// Same as `process()` but the windows are processed by `process_fn` instead of
+    /// the one given to `new()`, e.g. to borrow state owned by the caller.
+    pub fn process_with(&mut self, mut process_fn: impl FnMut(&[T], &mut [T])) {
+        while self.next_window() {
+            process_fn(&self.window_buffer, &mut self.process_buffer);
+            self.push_window();
+        }
+    }
+
+    /// Copy the next window into `window_buffer`, if the input has one.
+    fn next_window(&mut self) -> bool {
+        if self.input_buffer.len() < self.window_size {
+            return false;
+        }
+        self.input_buffer.copy_to_slice(0, &mut self.window_buffer);
+        true
+    }
+
+    /// Overlap-add `process_buffer` to the output and slide the input.
+    fn push_window(&mut self) {
+        let overlap_size = self.window_size - self.slide_size;
+        let offset = self.output_buffer.len() - overlap_size;
+        self.output_buffer
+            .add_slice(offset, &self.process_buffer[..overlap_size]);
+        self.output_buffer
+            .push_slice(&self.process_buffer[overlap_size..]);
+
+        self.input_buffer.discard(self.slide_size);
+    }
+}
+
 pub fn buffer_overlapping_write<T: Float>(least_size: usize, buffer: &mut Vec<T>, other: &[T]) {
     assert!(least_size <= other.len());
 
-    if buffer.len() < least_size {
-        buffer.resize(least_size, T::zero());
+    let overlap_size = other.len() - least_size;
+    // The overlap must fit in the buffer, also when it is longer than `least_size`.
+    let size = least_size.max(overlap_size);
+    if buffer.len() < size {
+        buffer.resize(size, T::zero());
     }
 
     let mut iter = other.iter().copied();
     let len = buffer.len();
-    let overlap_size = other.len() - least_size;
-    for i in len - overlap_size..len {
-        buffer[i] = buffer[i] + iter.next().unwrap();
+    for x in &mut buffer[len - overlap_size..] {
+        *x = *x + iter.next().unwrap();
     }
     buffer.extend(iter);
 }
+
+#[test]
+fn test_buffer_overlapping_write() {
+    let other = [1.0f64; 8];
+
+    // The overlap is longer than the slide.
+    let mut buffer = vec![];
+    buffer_overlapping_write(2, &mut buffer, &other);
+    assert_eq!(buffer, other);
+    buffer_overlapping_write(2, &mut buffer, &other);
+    assert_eq!(buffer, [1.0, 1.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 1.0, 1.0]);
+
+    // The overlap is shorter than the slide: the output starts after `least_size - overlap` zeros as before.
+    let mut buffer = vec![];
+    buffer_overlapping_write(6, &mut buffer, &other);
+    assert_eq!(buffer, [[0.0; 4].as_slice(), &other].concat());
+
+    let output = transform(8, 2, |b: &[f64]| b.to_vec(), &[1.0; 20]);
+    assert_eq!(output.len(), 26);
+}
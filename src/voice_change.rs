@@ -1,90 +1,281 @@
 use crate::{
-    fft::{fill_right_part_of_spectrum, fix_scale, Fft},
+    envelope::{Envelope, EnvelopeBuffers},
+    fft::{fill_right_part_of_spectrum, Fft},
     num_complex::Complex,
     num_traits::Zero,
-    pitch_shift::remove_aliasing,
+    pitch_shift::{remove_aliasing, PitchShiftOptions, PitchShifter},
+    spectral::SpectralProcessor,
     Float,
 };
 
 pub fn process_spectrum<T: Float>(
     slide_size: usize,
     fft: &Fft<T>,
-    pitch_shift: &mut impl FnMut(&[Complex<T>], T, usize) -> Vec<Complex<T>>,
-    envelope_order: usize,
+    pitch_shifter: &mut PitchShifter<T>,
+    envelope: Envelope,
     formant: T,
     pitch: T,
     spectrum: &mut [Complex<T>],
 ) {
-    assert!(0 < envelope_order && envelope_order < spectrum.len() / 2);
+    let mut buffers = Buffers::new(fft, spectrum.len());
+    buffers.process_spectrum(
+        fft,
+        |spectrum, shifted_spectrum| {
+            pitch_shifter.process_into(spectrum, pitch, slide_size, shifted_spectrum)
+        },
+        envelope,
+        formant,
+        pitch,
+        spectrum,
+    );
+}
 
-    let len = spectrum.len();
+/// Formant and pitch shifting as a `SpectralProcessor`.
+#[derive(Clone)]
+pub struct VoiceChange<T: Float> {
+    /// The estimator of the formants. The constructors set `Envelope::Cepstrum(envelope_order)`.
+    pub envelope: Envelope,
+    pub formant: T,
+    pub pitch: T,
+    slide_size: usize,
+    fft: Fft<T>,
+    pitch_shifter: PitchShifter<T>,
+    buffers: Buffers<T>,
+}
+
+impl<T: Float> VoiceChange<T> {
+    pub fn new(
+        window_size: usize,
+        slide_size: usize,
+        envelope_order: usize,
+        formant: T,
+        pitch: T,
+    ) -> Self {
+        Self::with_options(
+            window_size,
+            slide_size,
+            envelope_order,
+            formant,
+            pitch,
+            PitchShiftOptions::default(),
+        )
+    }
+
+    pub fn with_options(
+        window_size: usize,
+        slide_size: usize,
+        envelope_order: usize,
+        formant: T,
+        pitch: T,
+        options: PitchShiftOptions,
+    ) -> Self {
+        let fft = Fft::new(window_size);
+        let buffers = Buffers::new(&fft, window_size);
+        VoiceChange {
+            envelope: Envelope::Cepstrum(envelope_order),
+            formant,
+            pitch,
+            slide_size,
+            fft,
+            pitch_shifter: PitchShifter::with_options(window_size, options),
+            buffers,
+        }
+    }
 
-    // formant shift
-    let envelope = lift_spectrum(&fft, spectrum, |b| {
-        b[envelope_order..len - envelope_order + 1].fill(Complex::zero());
-    });
-    let shifted_envelope = formant_shift(&envelope, formant);
+    pub fn pitch_shifter_mut(&mut self) -> &mut PitchShifter<T> {
+        &mut self.pitch_shifter
+    }
+}
 
-    // pitch shift
-    let shifted_spectrum = pitch_shift(spectrum, pitch, slide_size);
+impl<T: Float> SpectralProcessor<T> for VoiceChange<T> {
+    /// `spectrum` can also be the `len / 2 + 1` bins of the spectrum.
+    fn process(&mut self, spectrum: &mut [Complex<T>]) {
+        let VoiceChange {
+            envelope,
+            formant,
+            pitch,
+            slide_size,
+            fft,
+            pitch_shifter,
+            buffers,
+        } = self;
+        buffers.process_spectrum(
+            fft,
+            |spectrum, shifted_spectrum| {
+                pitch_shifter.process_into(spectrum, *pitch, *slide_size, shifted_spectrum)
+            },
+            *envelope,
+            *formant,
+            *pitch,
+            spectrum,
+        );
+    }
 
-    // extract fine structure
-    let mut fine_structure = lift_spectrum(&fft, &shifted_spectrum, |b| {
-        b[..envelope_order].fill(Complex::zero());
-        b[len - envelope_order + 1..].fill(Complex::zero());
-    });
+    fn process_half(&mut self, half_spectrum: &mut [Complex<T>], _: &mut [Complex<T>]) {
+        self.process(half_spectrum);
+    }
 
-    remove_aliasing(pitch, &mut fine_structure);
+    fn reset(&mut self) {
+        self.pitch_shifter.reset();
+    }
 
-    for i in 0..=len / 2 {
-        let amp = (shifted_envelope[i] + fine_structure[i]).exp();
-        let phase = shifted_spectrum[i].arg();
-        spectrum[i] = Complex::from_polar(amp, phase);
+    fn frequency_ratio(&self) -> T {
+        self.pitch
     }
+}
 
-    fill_right_part_of_spectrum(spectrum);
+#[derive(Clone)]
+struct Buffers<T: Float> {
+    envelope_buffers: EnvelopeBuffers<T>,
+    envelope: Vec<T>,
+    shifted_envelope: Vec<T>,
+    shifted_spectrum: Vec<Complex<T>>,
+    fine_structure: Vec<T>,
+}
+
+impl<T: Float> Buffers<T> {
+    fn new(fft: &Fft<T>, len: usize) -> Self {
+        Buffers {
+            envelope_buffers: EnvelopeBuffers::new(fft),
+            envelope: vec![T::zero(); len],
+            shifted_envelope: vec![T::zero(); len],
+            shifted_spectrum: vec![Complex::zero(); len],
+            fine_structure: vec![T::zero(); len],
+        }
+    }
+
+    fn process_spectrum(
+        &mut self,
+        fft: &Fft<T>,
+        mut pitch_shift: impl FnMut(&[Complex<T>], &mut [Complex<T>]),
+        envelope: Envelope,
+        formant: T,
+        pitch: T,
+        spectrum: &mut [Complex<T>],
+    ) {
+        let len = fft.len();
+        let shifted_spectrum = &mut self.shifted_spectrum[..spectrum.len()];
+
+        // formant shift
+        envelope.estimate_into(
+            fft,
+            spectrum,
+            &mut self.envelope_buffers,
+            &mut self.envelope,
+        );
+        formant_shift_into(&self.envelope, formant, &mut self.shifted_envelope);
+
+        // pitch shift
+        pitch_shift(spectrum, shifted_spectrum);
+
+        // extract fine structure
+        envelope.estimate_into(
+            fft,
+            shifted_spectrum,
+            &mut self.envelope_buffers,
+            &mut self.fine_structure,
+        );
+        for (f, x) in self.fine_structure.iter_mut().zip(shifted_spectrum.iter()) {
+            *f = (x.norm() + T::epsilon()).ln() - *f;
+        }
+
+        remove_aliasing(pitch, &mut self.fine_structure);
+
+        for (i, x) in spectrum.iter_mut().enumerate().take(len / 2 + 1) {
+            let amp = (self.shifted_envelope[i] + self.fine_structure[i]).exp();
+            let phase = shifted_spectrum[i].arg();
+            *x = Complex::from_polar(amp, phase);
+        }
+
+        if spectrum.len() == len {
+            fill_right_part_of_spectrum(spectrum);
+        }
+    }
 }
 
 pub fn formant_shift<T: Float>(envelope: &[T], formant: T) -> Vec<T> {
+    let mut new_envelope = vec![T::zero(); envelope.len()];
+    formant_shift_into(envelope, formant, &mut new_envelope);
+    new_envelope
+}
+
+pub fn formant_shift_into<T: Float>(envelope: &[T], formant: T, new_envelope: &mut [T]) {
     let len = envelope.len();
     let negative = T::from(-1000.0).unwrap();
 
-    let mut new_envelope = vec![T::zero(); len];
-    for i in 0..len / 2 + 1 {
+    for (i, e) in new_envelope.iter_mut().enumerate().take(len / 2 + 1) {
         let j_f32 = T::from(i).unwrap() / formant;
         let j = j_f32.floor().to_usize().unwrap();
         let l = if j <= len / 2 { envelope[j] } else { negative };
-        let r = if j + 1 <= len / 2 {
+        let r = if j < len / 2 {
             envelope[j + 1]
         } else {
             negative
         };
         let x = j_f32 - T::from(j).unwrap();
-        new_envelope[i] = (T::one() - x) * l + x * r;
+        *e = (T::one() - x) * l + x * r;
     }
     for i in 1..len / 2 {
         new_envelope[len - i] = new_envelope[i];
     }
-    new_envelope
 }
 
+/// Compute the cepstrum of `spectrum`, modify it by `process` and return it to the log-amplitude spectrum.
+///
+/// `process` is given the real cepstrum, where it used to be given a complex buffer.
+/// The cepstrum of a real signal is real, so only the real part of that buffer was meaningful,
+/// and a `|b| b[range].fill(Complex::zero())` liftering becomes `|b| b[range].fill(0.0)`.
 pub fn lift_spectrum<T: Float>(
     fft: &Fft<T>,
     spectrum: &[Complex<T>],
-    mut process: impl FnMut(&mut Vec<Complex<T>>),
+    process: impl FnMut(&mut [T]),
 ) -> Vec<T> {
-    let mut cepstrum: Vec<_> = spectrum
-        .iter()
-        .map(|&x| Complex::from((x.norm() + T::epsilon()).ln()))
-        .collect();
+    let len = spectrum.len();
+    let mut cepstrum = vec![T::zero(); len];
+    let mut half_spectrum = vec![Complex::zero(); fft.half_len()];
+    let mut scratch = vec![Complex::zero(); fft.scratch_len()];
+    let mut envelope = vec![T::zero(); len];
+    lift_spectrum_into(
+        fft,
+        spectrum,
+        &mut cepstrum,
+        &mut half_spectrum,
+        &mut scratch,
+        process,
+        &mut envelope,
+    );
+    envelope
+}
 
-    fft.inverse(&mut cepstrum);
+/// Same as `lift_spectrum()` but writes into `envelope` using the given buffers.
+///
+/// Only the first `len / 2 + 1` bins of `spectrum` are read, so it must be of a real signal.
+pub fn lift_spectrum_into<T: Float>(
+    fft: &Fft<T>,
+    spectrum: &[Complex<T>],
+    cepstrum: &mut [T],
+    half_spectrum: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    mut process: impl FnMut(&mut [T]),
+    envelope: &mut [T],
+) {
+    let len = cepstrum.len();
 
-    process(&mut cepstrum);
+    for (c, x) in half_spectrum.iter_mut().zip(spectrum) {
+        *c = Complex::from((x.norm() + T::epsilon()).ln());
+    }
 
-    let mut envelope = cepstrum;
-    fft.forward(&mut envelope);
-    fix_scale(&mut envelope);
+    fft.inverse_real_with_scratch(half_spectrum, cepstrum, scratch);
 
-    envelope.into_iter().map(|x| x.re).collect()
+    process(cepstrum);
+
+    fft.forward_real_with_scratch(cepstrum, half_spectrum, scratch);
+
+    let scale = T::one() / T::from(len).unwrap();
+    for (e, x) in envelope.iter_mut().zip(half_spectrum.iter()) {
+        *e = x.re * scale;
+    }
+    for i in half_spectrum.len()..len {
+        envelope[i] = envelope[len - i];
+    }
 }
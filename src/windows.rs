@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::num_traits::{Float, FloatConst};
 
 pub fn rectangular_window<T: Float + FloatConst>(size: usize) -> Vec<T> {
@@ -51,6 +53,142 @@ pub fn blackman_window_default<T: Float + FloatConst>(size: usize) -> Vec<T> {
     blackman_window(T::from(0.16).unwrap(), size)
 }
 
+/// Why a pair of windows can't reconstruct a signal by overlap-add. See `check_reconstruction()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowError {
+    LengthMismatch {
+        pre: usize,
+        post: usize,
+    },
+    InvalidSlide {
+        slide_size: usize,
+        window_size: usize,
+    },
+    /// The overlapping products of the windows vanish at `position` in each slide,
+    /// so the signal there is lost (the NOLA condition is not met).
+    Gap {
+        position: usize,
+    },
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::LengthMismatch { pre, post } => write!(
+                f,
+                "the pre window has {pre} samples but the post window has {post}"
+            ),
+            WindowError::InvalidSlide {
+                slide_size,
+                window_size,
+            } => write!(
+                f,
+                "the slide size {slide_size} is not within 1..={window_size}"
+            ),
+            WindowError::Gap { position } => write!(
+                f,
+                "the overlap-add of the windows is zero at sample {position} of each slide, \
+                 so the signal can't be reconstructed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WindowError {}
+
+/// How a pair of windows reconstructs a signal by overlap-add. See `check_reconstruction()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconstruction<T> {
+    /// The gain of the overlap-add at each sample of a slide,
+    /// the sum of `pre_window[i] * post_window[i]` over the `i`s at that sample.
+    pub overlap: Vec<T>,
+    /// `(max - min) / max` of `overlap`, which is 0 if the windows meet the COLA condition,
+    /// that is, a constant scale is enough for perfect reconstruction.
+    pub ripple: T,
+}
+
+impl<T: Float> Reconstruction<T> {
+    /// `post_window` divided by the overlap at each sample,
+    /// so that the overlap-add of unmodified windows gives back the input exactly.
+    pub fn normalize(&self, post_window: &[T]) -> Vec<T> {
+        let slide_size = self.overlap.len();
+        post_window
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| w / self.overlap[i % slide_size])
+            .collect()
+    }
+}
+
+/// Check that `pre_window` and `post_window` sliding by `slide_size` can reconstruct a signal by overlap-add,
+/// and find the normalization for it.
+///
+/// The windows are treated as multiplied before the FFT and after the inverse FFT with `1 / len` scaling.
+/// The overlap is counted as zero below 1e-4 times its maximum.
+///
+/// # Example
+/// ```
+/// # use voiche::windows::{check_reconstruction, hann_window, trapezoid_window, WindowError};
+/// let hann = hann_window::<f64>(1024);
+/// let trapezoid = trapezoid_window(1024, 1024 - 256);
+///
+/// assert!(check_reconstruction(&hann, &hann, 256).unwrap().ripple < 1e-9);
+/// assert!(0.01 < check_reconstruction(&hann, &trapezoid, 256).unwrap().ripple);
+/// assert_eq!(
+///     check_reconstruction(&hann, &hann, 1024),
+///     Err(WindowError::Gap { position: 0 })
+/// );
+/// ```
+pub fn check_reconstruction<T: Float>(
+    pre_window: &[T],
+    post_window: &[T],
+    slide_size: usize,
+) -> Result<Reconstruction<T>, WindowError> {
+    let window_size = pre_window.len();
+    if window_size != post_window.len() {
+        return Err(WindowError::LengthMismatch {
+            pre: window_size,
+            post: post_window.len(),
+        });
+    }
+    if slide_size == 0 || window_size < slide_size {
+        return Err(WindowError::InvalidSlide {
+            slide_size,
+            window_size,
+        });
+    }
+
+    let mut overlap = vec![T::zero(); slide_size];
+    for (i, (&x, &y)) in pre_window.iter().zip(post_window).enumerate() {
+        overlap[i % slide_size] = overlap[i % slide_size] + x * y;
+    }
+    let max = overlap.iter().fold(T::neg_infinity(), |a, &x| a.max(x));
+    let min = overlap.iter().fold(T::infinity(), |a, &x| a.min(x));
+    let peak = max.abs().max(min.abs());
+    let threshold = peak * T::from(1e-4).unwrap();
+    if let Some(position) = overlap.iter().position(|x| x.abs() <= threshold) {
+        return Err(WindowError::Gap { position });
+    }
+    Ok(Reconstruction {
+        ripple: (max - min) / peak,
+        overlap,
+    })
+}
+
+/// `post_window` normalized for perfect reconstruction with `pre_window`, by `check_reconstruction()`.
+///
+/// Panics with a description of the problem if the windows can't reconstruct a signal.
+pub fn synthesis_window<T: Float>(
+    pre_window: &[T],
+    post_window: &[T],
+    slide_size: usize,
+) -> Vec<T> {
+    match check_reconstruction(pre_window, post_window, slide_size) {
+        Ok(reconstruction) => reconstruction.normalize(post_window),
+        Err(err) => panic!("{}", err),
+    }
+}
+
 #[test]
 fn test() {
     dbg!(trapezoid_window::<f32>(8, 0));
@@ -61,4 +199,37 @@ fn test() {
     dbg!(hann_window::<f32>(10));
     dbg!(hamming_window::<f32>(10));
     dbg!(blackman_window::<f32>(0.16, 10));
+
+    // Perfect reconstruction with normalization, also without COLA.
+    let hann = hann_window::<f64>(64);
+    for (post, slide_size) in [
+        (hann.clone(), 16),
+        (hann.clone(), 32),
+        (rectangular_window(64), 32),
+        (trapezoid_window(64, 64 - 16), 16),
+        (trapezoid_window(64, 16), 16),
+    ] {
+        let post = synthesis_window(&hann, &post, slide_size);
+        let mut sum = vec![0.0; 64 * 3];
+        for start in (0..sum.len() - 64).step_by(slide_size) {
+            for (i, (x, y)) in hann.iter().zip(&post).enumerate() {
+                sum[start + i] += x * y;
+            }
+        }
+        assert!(sum[64..128].iter().all(|x| (x - 1.0).abs() < 1e-9));
+    }
+    assert!((check_reconstruction(&hann, &hann, 32).unwrap().ripple - 0.5).abs() < 1e-9);
+
+    assert_eq!(
+        check_reconstruction(&hann, &hann[1..], 16),
+        Err(WindowError::LengthMismatch { pre: 64, post: 63 })
+    );
+    assert!(matches!(
+        check_reconstruction(&hann, &hann, 0),
+        Err(WindowError::InvalidSlide { .. })
+    ));
+    assert_eq!(
+        check_reconstruction(&hann, &trapezoid_window(64, 10), 64),
+        Err(WindowError::Gap { position: 0 })
+    );
 }
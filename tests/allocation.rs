@@ -0,0 +1,185 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use voiche::{
+    fft::Fft,
+    num_complex::Complex,
+    pitch_shift::PitchShiftProcessor,
+    pitch_tracker::{PitchTracker, Smoothing},
+    spectral::{SpectralDriver, SpectralProcessor},
+    transform::{RingTransformer, Transformer},
+    voice_change::VoiceChange,
+    windows,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|c| c.get())
+}
+
+#[test]
+fn ring_transformer_does_not_allocate() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let block_size = 100;
+    let process = |input: &[f32], output: &mut [f32]| {
+        for (y, x) in output.iter_mut().zip(input) {
+            *y = x * 0.5;
+        }
+    };
+
+    let signal: Vec<f32> = (0..window_size * 8)
+        .map(|i| (i as f32 * 0.01).sin())
+        .collect();
+    let mut expected = Vec::new();
+    let mut transformer = Transformer::new(window_size, slide_size, |buf: &[f32]| {
+        let mut output = vec![0.0; buf.len()];
+        process(buf, &mut output);
+        output
+    });
+    let mut output = [0.0; 100];
+    for chunk in signal.chunks(block_size) {
+        transformer.input_slice(chunk);
+        transformer.process();
+        while transformer.output_slice_exact(&mut output[..chunk.len()]) {
+            expected.extend_from_slice(&output[..chunk.len()]);
+        }
+    }
+
+    let mut actual = Vec::with_capacity(signal.len());
+    let mut transformer = RingTransformer::new(window_size, slide_size, block_size, process);
+    let mut output = [0.0; 100];
+
+    let before = allocations();
+    for chunk in signal.chunks(block_size) {
+        transformer.input_slice(chunk);
+        transformer.process();
+        while transformer.output_slice_exact(&mut output[..chunk.len()]) {
+            actual.extend_from_slice(&output[..chunk.len()]);
+        }
+    }
+    assert_eq!(allocations(), before);
+
+    assert!(!actual.is_empty());
+    assert_eq!(actual, expected);
+
+    // The same with the windows processed by a closure given to each call.
+    let mut actual = Vec::with_capacity(signal.len());
+    let mut transformer = RingTransformer::new(
+        window_size,
+        slide_size,
+        block_size,
+        |_: &[f32], _: &mut [f32]| unreachable!(),
+    );
+
+    let before = allocations();
+    for chunk in signal.chunks(block_size) {
+        transformer.input_slice(chunk);
+        transformer.process_with(process);
+        while transformer.output_slice_exact(&mut output[..chunk.len()]) {
+            actual.extend_from_slice(&output[..chunk.len()]);
+        }
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn spectral_driver_does_not_allocate() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let driver = SpectralDriver::new(
+        windows::hann_window(window_size),
+        windows::trapezoid_window(window_size, window_size - slide_size),
+        slide_size,
+        VoiceChange::new(window_size, slide_size, window_size / 8, 1.2, 0.8)
+            .chain(PitchShiftProcessor::new(window_size, slide_size, 1.1))
+            .chain(|spectrum: &mut [Complex<f32>]| {
+                for x in spectrum.iter_mut() {
+                    *x *= 0.5;
+                }
+            }),
+    );
+    let mut transformer = RingTransformer::new(window_size, slide_size, slide_size, {
+        let mut driver = driver;
+        move |input: &[f32], output: &mut [f32]| driver.process(input, output)
+    });
+
+    let signal: Vec<f32> = (0..window_size * 8)
+        .map(|i| (i as f32 * 0.05).sin())
+        .collect();
+    let mut output = [0.0; 256];
+
+    let before = allocations();
+    for chunk in signal.chunks(slide_size) {
+        transformer.input_slice(chunk);
+        transformer.process();
+        while transformer.output_slice_exact(&mut output) {}
+    }
+    assert_eq!(allocations(), before);
+}
+
+#[test]
+fn fft_does_not_allocate() {
+    let len = 1024;
+    let fft = Fft::<f32>::new(len);
+    let mut buffer = vec![0.5; len];
+    let mut half_spectrum = vec![Complex::from(0.0); fft.half_len()];
+    let mut spectrum = vec![Complex::from(0.0); len];
+
+    let before = allocations();
+    fft.forward_real(&mut buffer, &mut half_spectrum);
+    fft.inverse_real(&mut half_spectrum, &mut buffer);
+    fft.forward(&mut spectrum);
+    fft.inverse(&mut spectrum);
+    assert_eq!(allocations(), before);
+}
+
+#[test]
+fn pitch_tracker_does_not_allocate() {
+    let window_size = 1024;
+    let mut tracker = PitchTracker::<f32, _>::with_mpm(window_size, 16000);
+    let signal: Vec<f32> = (0..window_size * 4)
+        .map(|i| (i as f32 * 0.05).sin())
+        .collect();
+    let windows: Vec<_> = signal.windows(window_size).step_by(256).collect();
+
+    let before = allocations();
+    for window in &windows {
+        assert!(tracker.process(window).voiced);
+    }
+    assert_eq!(allocations(), before);
+
+    tracker.smoothing = Smoothing::OctaveCorrection;
+    let before = allocations();
+    for window in &windows {
+        assert!(tracker.process(window).voiced);
+    }
+    assert_eq!(allocations(), before);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{detect, tone, SAMPLE_RATE};
+use voiche::{
+    api,
+    auto_tune::{AutoTune, NoteEvent, Scale},
+    transform::transform,
+    windows,
+};
+
+#[test]
+fn snaps_to_scale() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+
+    // 212 Hz is between G#3 (207.7 Hz) and A3 (220 Hz).
+    let buf = tone(212.0, SAMPLE_RATE as usize);
+    for (scale, expected) in [(Scale::Chromatic, 207.65), (Scale::Major, 220.0)] {
+        let mut auto_tune = AutoTune::new(SAMPLE_RATE, slide_size);
+        auto_tune.scale = scale;
+        auto_tune.retune_speed = 10.0;
+        let output = transform(
+            window_size,
+            slide_size,
+            api::auto_tune(
+                windows::hann_window(window_size),
+                windows::trapezoid_window(window_size, window_size - slide_size),
+                slide_size,
+                SAMPLE_RATE,
+                auto_tune,
+            ),
+            &buf,
+        );
+        assert!((detect(&output, 0.5) - expected).abs() < 2.0);
+    }
+}
+
+#[test]
+fn follows_notes() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let buf = tone(212.0, SAMPLE_RATE as usize);
+
+    // C4 is held in the first half, then the scale takes over.
+    let notes = vec![
+        NoteEvent {
+            time: 0.0,
+            note: 60,
+            on: true,
+        },
+        NoteEvent {
+            time: 0.5,
+            note: 60,
+            on: false,
+        },
+    ];
+    let mut auto_tune = AutoTune::new(SAMPLE_RATE, slide_size);
+    auto_tune.retune_speed = 10.0;
+    let output = transform(
+        window_size,
+        slide_size,
+        api::auto_tune_with_notes(
+            windows::hann_window(window_size),
+            windows::trapezoid_window(window_size, window_size - slide_size),
+            slide_size,
+            SAMPLE_RATE,
+            auto_tune,
+            notes,
+        ),
+        &buf,
+    );
+    assert!((detect(&output, 0.2) - 261.63).abs() < 3.0);
+    assert!((detect(&output, 0.75) - 207.65).abs() < 2.0);
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::noise;
+use voiche::{
+    api,
+    cross_synthesis::Analysis,
+    envelope::Envelope,
+    fft::Fft,
+    num_complex::Complex,
+    transform::{transform2, Transformer2},
+    windows,
+};
+
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+
+/// A pulse train with a period of 80 samples through a resonance at bin 100 of the window.
+fn voice(len: usize) -> Vec<f64> {
+    let (radius, angle) = (0.98f64, std::f64::consts::TAU * 100.0 / WINDOW_SIZE as f64);
+    let mut buf = vec![0.0; len];
+    for i in 0..len {
+        let pulse = if i % 80 == 0 { 1.0 } else { 0.0 };
+        let y1 = if 0 < i { buf[i - 1] } else { 0.0 };
+        let y2 = if 1 < i { buf[i - 2] } else { 0.0 };
+        buf[i] = pulse + 2.0 * radius * angle.cos() * y1 - radius * radius * y2;
+    }
+    buf
+}
+
+fn cross_synthesis(analysis: Analysis) -> impl FnMut(&[f64], &[f64]) -> Vec<f64> {
+    api::cross_synthesis(
+        windows::hann_window(WINDOW_SIZE),
+        windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+        SLIDE_SIZE,
+        analysis,
+    )
+}
+
+#[test]
+fn formant_moves_to_carrier() {
+    let modulator = voice(16000);
+    let carrier = noise(16000, 1);
+    let fft = Fft::new(WINDOW_SIZE);
+    let window = windows::hann_window::<f64>(WINDOW_SIZE);
+
+    for analysis in [
+        Analysis::Envelope(Envelope::Cepstrum(30)),
+        Analysis::Bands(32),
+    ] {
+        let output = transform2(
+            WINDOW_SIZE,
+            SLIDE_SIZE,
+            cross_synthesis(analysis),
+            &modulator,
+            &carrier,
+        );
+
+        // Smoothed spectrum of the middle of the output.
+        let mut spectrum: Vec<_> = output[8000..8000 + WINDOW_SIZE]
+            .iter()
+            .zip(&window)
+            .map(|(&x, &w)| Complex::new(x * w, 0.0))
+            .collect();
+        fft.forward(&mut spectrum);
+        let smoothed = Envelope::Cepstrum(20).estimate(&fft, &spectrum);
+        let peak = (1..WINDOW_SIZE / 2)
+            .max_by(|&a, &b| smoothed[a].partial_cmp(&smoothed[b]).unwrap())
+            .unwrap();
+        assert!(peak.abs_diff(100) <= 8, "{:?} {}", analysis, peak);
+    }
+}
+
+#[test]
+fn streaming_matches_batch() {
+    let modulator = voice(8000);
+    let carrier = noise(6000, 1);
+    let analysis = Analysis::Bands(16);
+
+    // The transformer starts with a window of silence but the last slide.
+    let pad = |buf: &[f64]| [vec![0.0; WINDOW_SIZE - SLIDE_SIZE], buf.to_vec()].concat();
+    let batch = transform2(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        cross_synthesis(analysis),
+        &pad(&modulator),
+        &pad(&carrier),
+    );
+
+    let mut transformer = Transformer2::new(WINDOW_SIZE, SLIDE_SIZE, cross_synthesis(analysis));
+    let mut streamed = Vec::new();
+    let mut block = vec![0.0; 100];
+    for (i, chunk) in modulator.chunks(100).enumerate() {
+        let carrier = carrier.get(i * 100..).unwrap_or(&[]);
+        transformer.input_slices(chunk, &carrier[..carrier.len().min(100)]);
+        transformer.process();
+        while transformer.output_slice_exact(&mut block) {
+            streamed.extend_from_slice(&block);
+        }
+    }
+    transformer.finish(&mut streamed);
+
+    // The shorter carrier is padded with zeros in both.
+    let len = streamed.len().min(batch.len()) - WINDOW_SIZE;
+    assert!(7000 < len);
+    assert!(streamed[..len]
+        .iter()
+        .zip(&batch[..len])
+        .all(|(a, b)| (a - b).abs() < 1e-9));
+}
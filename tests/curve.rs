@@ -0,0 +1,31 @@
+mod common;
+
+use common::{detect, tone, SAMPLE_RATE};
+use voiche::{api, curve::Breakpoints, transform::transform, windows};
+
+#[test]
+fn pitch_follows_curve() {
+    let window_size = 1024;
+    let slide_size = window_size / 4;
+    let f0 = 200.0;
+    let buf = tone(f0, SAMPLE_RATE as usize * 2);
+
+    // Hold 1.0 for the first half second, glide to 1.5 and hold it.
+    let pitch = Breakpoints::new(vec![(0.5, 1.0), (1.5, 1.5)]);
+    let output = transform(
+        window_size,
+        slide_size,
+        api::pitch_shift_with_curve(
+            windows::hann_window(window_size),
+            windows::trapezoid_window(window_size, window_size - slide_size),
+            slide_size,
+            SAMPLE_RATE,
+            pitch,
+        ),
+        &buf,
+    );
+
+    assert!((detect(&output, 0.2) - f0).abs() < 5.0);
+    assert!((detect(&output, 1.0) - f0 * 1.25).abs() < 15.0);
+    assert!((detect(&output, 1.8) - f0 * 1.5).abs() < 5.0);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{detect, tone, SAMPLE_RATE};
+use voiche::{
+    api,
+    envelope::Envelope,
+    fft::Fft,
+    harmonizer::{Harmonizer, Interval, Voice},
+    pitch_detection::Yin,
+    pitch_tracker::PitchTracker,
+    transform::transform,
+    windows,
+};
+
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+
+fn rms(buf: &[f64]) -> f64 {
+    let middle = &buf[buf.len() / 4..buf.len() * 3 / 4];
+    (middle.iter().map(|x| x * x).sum::<f64>() / middle.len() as f64).sqrt()
+}
+
+fn harmonizer(voices: Vec<Voice<f64>>) -> Harmonizer<f64, Yin<f64>> {
+    let mut harmonizer = Harmonizer::new(
+        windows::hann_window(WINDOW_SIZE),
+        windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+        SLIDE_SIZE,
+        PitchTracker::new(Yin::new(WINDOW_SIZE, 20.0, 400.0), SAMPLE_RATE),
+        voices,
+    );
+    harmonizer.dry = 0.0;
+    harmonizer.envelope = Envelope::Cepstrum(32);
+    harmonizer
+}
+
+#[test]
+fn scale_degrees() {
+    // A third below A3 in C major is F3.
+    let buf = tone(220.0, SAMPLE_RATE as usize);
+    let output = transform(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        api::harmonizer(harmonizer(vec![Voice::new(Interval::Degrees(-2))])),
+        &buf,
+    );
+    assert!((detect(&output, 0.5) - 174.61).abs() < 2.0);
+}
+
+#[test]
+fn stereo() {
+    let buf = tone(220.0, SAMPLE_RATE as usize);
+    let mut fifth = Voice::new(Interval::Semitones(7.0));
+    fifth.pan = -1.0;
+    fifth.preserve_formant = false;
+    let [left, right] = api::harmonizer_stereo(harmonizer(vec![fifth]), &buf);
+    assert_eq!(left.len(), right.len());
+    // The same as shifting the pitch and the formants alone.
+    let pitch = (7.0f64 / 12.0).exp2();
+    let expected = transform(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        api::voice_change(
+            windows::hann_window(WINDOW_SIZE),
+            windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+            SLIDE_SIZE,
+            32,
+            pitch,
+            pitch,
+        ),
+        &buf,
+    );
+    assert_eq!(left.len(), expected.len());
+    assert!(left.iter().zip(&expected).all(|(x, y)| (x - y).abs() < 1e-9));
+    assert!(rms(&right) < 1e-6);
+
+    // The dry signal is centered with unit gain.
+    let mut harmonizer = harmonizer(vec![]);
+    harmonizer.dry = 1.0;
+    let [left, right] = api::harmonizer_stereo(harmonizer, &buf);
+    let fft = Fft::new(WINDOW_SIZE);
+    let pre_window = windows::hann_window(WINDOW_SIZE);
+    let post_window = windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE);
+    let expected = transform(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        |b: &[f64]| api::retouch_spectrum(&fft, &pre_window, &post_window, SLIDE_SIZE, b, |_| {}),
+        &buf,
+    );
+    assert_eq!(left, right);
+    assert!(left.iter().zip(&expected).all(|(x, y)| (x - y).abs() < 1e-9));
+}
+
+#[test]
+#[should_panic(expected = "the input must have the window size")]
+fn short_input_is_rejected() {
+    let mut output = vec![0.0; WINDOW_SIZE];
+    harmonizer(vec![]).process(&[0.0; WINDOW_SIZE / 2], &mut [&mut output]);
+}
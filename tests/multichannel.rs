@@ -0,0 +1,135 @@
+mod common;
+
+use common::{tone, SAMPLE_RATE};
+use voiche::{
+    api,
+    spectral::Linking,
+    transform::{deinterleave, interleave, transform, transform_multi, MultiTransformer},
+    windows,
+};
+
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+/// Half a second.
+const LEN: usize = SAMPLE_RATE as usize / 2;
+
+fn max_difference(a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    a.iter().zip(b).fold(0.0, |m, (x, y)| m.max((x - y).abs()))
+}
+
+fn pitch_shift(linking: Linking) -> impl FnMut(&[Vec<f64>]) -> Vec<Vec<f64>> {
+    api::pitch_shift_multi(
+        windows::hann_window(WINDOW_SIZE),
+        windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+        SLIDE_SIZE,
+        2,
+        linking,
+        1.5,
+    )
+}
+
+#[test]
+fn interleaving() {
+    let planar = vec![tone(220.0, LEN), tone(330.0, LEN)];
+    let interleaved = interleave(&planar);
+    assert_eq!(
+        interleaved[..4],
+        [planar[0][0], planar[1][0], planar[0][1], planar[1][1]]
+    );
+    assert_eq!(deinterleave(&interleaved, 2), planar);
+}
+
+#[test]
+fn independent_matches_mono() {
+    let planar = vec![tone(220.0, LEN), tone(330.0, LEN)];
+    let output = transform_multi(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        pitch_shift(Linking::Independent),
+        &planar,
+    );
+    for (input, output) in planar.iter().zip(&output) {
+        let mono = transform(
+            WINDOW_SIZE,
+            SLIDE_SIZE,
+            api::pitch_shift(
+                windows::hann_window(WINDOW_SIZE),
+                windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+                SLIDE_SIZE,
+                1.5,
+            ),
+            input,
+        );
+        assert!(max_difference(output, &mono) < 1e-9);
+    }
+}
+
+#[test]
+fn linked_keeps_channels_coherent() {
+    // The same signal at different levels stays the same up to the level.
+    let left = tone(220.0, LEN);
+    let right: Vec<_> = left.iter().map(|x| x * 0.5).collect();
+    let output = transform_multi(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        pitch_shift(Linking::Linked),
+        &[left, right],
+    );
+    let half: Vec<_> = output[0].iter().map(|x| x * 0.5).collect();
+    assert!(max_difference(&output[1], &half) < 1e-9);
+    assert!(0.01 < output[0].iter().fold(0.0f64, |m, x| m.max(x.abs())));
+}
+
+#[test]
+fn streaming_matches_batch() {
+    let planar = vec![tone(220.0, LEN), tone(330.0, LEN)];
+    // The transformer starts with a window of silence but the last slide.
+    let padded: Vec<_> = planar
+        .iter()
+        .map(|buf| [vec![0.0; WINDOW_SIZE - SLIDE_SIZE], buf.clone()].concat())
+        .collect();
+    let batch = transform_multi(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        pitch_shift(Linking::Linked),
+        &padded,
+    );
+
+    let mut transformer =
+        MultiTransformer::new(WINDOW_SIZE, SLIDE_SIZE, 2, pitch_shift(Linking::Linked));
+    let interleaved = interleave(&planar);
+    let mut streamed = Vec::new();
+    let mut block = vec![0.0; 2 * 100];
+    for chunk in interleaved.chunks(block.len()) {
+        transformer.input_interleaved(chunk);
+        transformer.process();
+        while transformer.output_interleaved_exact(&mut block) {
+            streamed.extend_from_slice(&block);
+        }
+    }
+    let mut rest = vec![vec![]; 2];
+    transformer.finish(&mut rest);
+    let mut streamed = deinterleave(&streamed, 2);
+    for (streamed, rest) in streamed.iter_mut().zip(rest) {
+        streamed.extend(rest);
+    }
+
+    for (streamed, batch) in streamed.iter().zip(&batch) {
+        let len = streamed.len().min(batch.len()) - WINDOW_SIZE;
+        assert!(LEN / 2 < len);
+        assert!(max_difference(&streamed[..len], &batch[..len]) < 1e-9);
+    }
+}
+
+#[test]
+#[should_panic(expected = "multiple of the channels")]
+fn partial_frame_is_rejected() {
+    let mut transformer = MultiTransformer::new(
+        WINDOW_SIZE,
+        SLIDE_SIZE,
+        2,
+        pitch_shift(Linking::Independent),
+    );
+    transformer.input_interleaved(&[0.0; 3]);
+}
@@ -0,0 +1,77 @@
+use voiche::{
+    num_complex::Complex,
+    pitch_shift::{wrap_phase, PhaseLocking, PitchShiftOptions, PitchShiftProcessor},
+    spectral::{SpectralDriver, SpectralProcessor},
+    transform::transform,
+    windows,
+};
+
+const SAMPLE_RATE: f64 = 16000.0;
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+
+fn harmonic_signal(f0: f64, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE;
+            (1..=8)
+                .map(|k| (std::f64::consts::TAU * f0 * k as f64 * t).sin() / k as f64)
+                .sum::<f64>()
+                * 0.2
+        })
+        .collect()
+}
+
+/// Pitch shift `buf` and measure the mean deviation of the phase differences
+/// between each harmonic's peak bin and its neighbours in the synthesized spectra
+/// from that of a stationary sinusoid, which is π for a Hann window.
+fn phase_incoherence(buf: &[f64], f0: f64, pitch: f64, phase_locking: PhaseLocking) -> f64 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    let mut frame = 0;
+    let measure = |spectrum: &mut [Complex<f64>]| {
+        frame += 1;
+        if frame < 4 {
+            return;
+        }
+        for k in 1..=8 {
+            let bin = (f0 * pitch * k as f64 * WINDOW_SIZE as f64 / SAMPLE_RATE).round() as usize;
+            let peak = (bin - 1..=bin + 1)
+                .max_by(|&a, &b| spectrum[a].norm().total_cmp(&spectrum[b].norm()))
+                .unwrap();
+            for neighbour in [peak - 1, peak + 1] {
+                let diff = spectrum[neighbour].arg() - spectrum[peak].arg();
+                sum += wrap_phase(diff - std::f64::consts::PI).abs();
+                count += 1;
+            }
+        }
+    };
+    let options = PitchShiftOptions {
+        phase_locking,
+        ..Default::default()
+    };
+    let mut driver = SpectralDriver::new(
+        windows::hann_window(WINDOW_SIZE),
+        windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE),
+        SLIDE_SIZE,
+        PitchShiftProcessor::with_options(WINDOW_SIZE, SLIDE_SIZE, pitch, options).chain(measure),
+    );
+    transform(WINDOW_SIZE, SLIDE_SIZE, |b| driver.process_to_vec(b), buf);
+    drop(driver);
+    sum / count as f64
+}
+
+#[test]
+fn phase_locking_improves_phase_coherence() {
+    let f0 = 190.0;
+    let buf = harmonic_signal(f0, 16000);
+
+    for pitch in [0.8, 1.26] {
+        let off = phase_incoherence(&buf, f0, pitch, PhaseLocking::Off);
+        let identity = phase_incoherence(&buf, f0, pitch, PhaseLocking::Identity);
+        let scaled = phase_incoherence(&buf, f0, pitch, PhaseLocking::Scaled);
+
+        assert!(identity < off * 0.5);
+        assert!(scaled < off * 0.5);
+    }
+}
@@ -0,0 +1,36 @@
+use voiche::{
+    fft::Fft,
+    pitch_detection::{compute_peaks, pitch_detect, Mpm, PitchDetector},
+    windows,
+};
+
+#[test]
+fn peak_is_at_the_vertex() {
+    let nsdf = [1.0, 0.0, -1.0, 0.0, 0.6, 1.0, 0.6, 0.0, -1.0, 0.0, 0.0];
+    assert_eq!(compute_peaks(&nsdf), vec![(5.0, 1.0)]);
+}
+
+#[test]
+fn sine_wavelength() {
+    let window_size = 1024;
+    let fft = Fft::new(window_size);
+    let window = windows::hann_window(window_size);
+
+    // The taper of the NSDF lowers long wavelengths a little, so only short ones are exact.
+    // Before the vertex was fixed, they came out one sample short.
+    for wavelength in [20.0, 25.0, 32.0, 40.0] {
+        let buf: Vec<f64> = (0..window_size)
+            .map(|i| (std::f64::consts::TAU * i as f64 / wavelength).sin())
+            .collect();
+        let (detected, _) = pitch_detect(&fft, &window, &buf, 10.0, 0.1).unwrap();
+        assert!(
+            (detected - wavelength).abs() < 0.05,
+            "{wavelength}: {detected}"
+        );
+        let (detected, _) = Mpm::new(window.clone(), 10.0, 0.1).detect(&buf).unwrap();
+        assert!(
+            (detected - wavelength).abs() < 0.05,
+            "{wavelength}: {detected}"
+        );
+    }
+}
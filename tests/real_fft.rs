@@ -0,0 +1,142 @@
+use voiche::{
+    api,
+    fft::{fix_scale, Fft},
+    num_complex::Complex,
+    pitch_detection::compute_nsdf,
+    pitch_shift::PitchShiftProcessor,
+    spectral::{SpectralDriver, SpectralProcessor},
+    transform::transform,
+    voice_change::{lift_spectrum, VoiceChange},
+    windows,
+};
+
+const LEN: usize = 512;
+
+fn signal() -> Vec<f64> {
+    (0..LEN)
+        .map(|i| {
+            let t = i as f64;
+            (t * 0.07).sin() + 0.5 * (t * 0.31).cos() + 0.1 * (t * 1.3).sin()
+        })
+        .collect()
+}
+
+fn spectrum(fft: &Fft<f64>, buf: &[f64]) -> Vec<Complex<f64>> {
+    let mut spectrum: Vec<_> = buf.iter().copied().map(Complex::from).collect();
+    fft.forward(&mut spectrum);
+    spectrum
+}
+
+fn assert_close(x: &[f64], y: &[f64]) {
+    assert_eq!(x.len(), y.len());
+    for (i, (x, y)) in x.iter().zip(y).enumerate() {
+        assert!((x - y).abs() < 1e-9, "{i}: {x} != {y}");
+    }
+}
+
+#[test]
+fn retouch_spectrum_matches_complex_path() {
+    let slide_size = LEN / 4;
+    let fft = Fft::new(LEN);
+    let pre_window = windows::hann_window(LEN);
+    let post_window = windows::hann_window(LEN);
+    let buf = signal();
+    // A real gain, symmetric around the Nyquist frequency.
+    let process = |spectrum: &mut [Complex<f64>]| {
+        for (i, x) in spectrum.iter_mut().enumerate() {
+            *x *= 1.0 / (1.0 + i.min(LEN - i) as f64 / 16.0);
+        }
+    };
+
+    let mut spec: Vec<_> = buf
+        .iter()
+        .zip(&pre_window)
+        .map(|(x, w)| Complex::from(x * w))
+        .collect();
+    fft.forward(&mut spec);
+    process(&mut spec);
+    fft.inverse(&mut spec);
+    fix_scale(&mut spec);
+    let synthesis_window = windows::synthesis_window(&pre_window, &post_window, slide_size);
+    let expected: Vec<_> = spec
+        .iter()
+        .zip(&synthesis_window)
+        .map(|(x, w)| x.re * w)
+        .collect();
+
+    let actual = api::retouch_spectrum(&fft, &pre_window, &post_window, slide_size, &buf, process);
+    assert_close(&actual, &expected);
+    let actual =
+        api::retouch_spectrum_normalized(&fft, &pre_window, &synthesis_window, &buf, process);
+    assert_close(&actual, &expected);
+}
+
+#[test]
+fn lift_spectrum_matches_complex_path() {
+    let order = 24;
+    let fft = Fft::new(LEN);
+    let spectrum = spectrum(&fft, &signal());
+
+    let mut cepstrum: Vec<_> = spectrum
+        .iter()
+        .map(|x| Complex::from((x.norm() + f64::EPSILON).ln()))
+        .collect();
+    fft.inverse(&mut cepstrum);
+    cepstrum[order..LEN - order + 1].fill(Complex::from(0.0));
+    fft.forward(&mut cepstrum);
+    fix_scale(&mut cepstrum);
+    let expected: Vec<_> = cepstrum.iter().map(|x| x.re).collect();
+
+    let actual = lift_spectrum(&fft, &spectrum, |b| b[order..LEN - order + 1].fill(0.0));
+    assert_close(&actual, &expected);
+}
+
+#[test]
+fn nsdf_matches_complex_path() {
+    let fft = Fft::new(LEN);
+    let buf = signal();
+
+    let mut acf: Vec<_> = spectrum(&fft, &buf)
+        .iter()
+        .map(|x| Complex::from(x.norm_sqr()))
+        .collect();
+    fft.inverse(&mut acf);
+    let mut expected = vec![0.0; LEN];
+    let mut m = f64::EPSILON;
+    for i in 0..LEN {
+        let inv = LEN - i - 1;
+        m += buf[i].powi(2) + buf[inv].powi(2);
+        expected[inv] = 2.0 * acf[inv].re / (m * LEN as f64);
+    }
+
+    assert_close(&compute_nsdf(&fft, &buf), &expected);
+}
+
+fn drive<P: SpectralProcessor<f64>>(processor: P) -> Vec<f64> {
+    let slide_size = LEN / 4;
+    let buf: Vec<f64> = (0..LEN * 4).map(|i| (i as f64 * 0.05).sin()).collect();
+    let mut driver = SpectralDriver::new(
+        windows::hann_window(LEN),
+        windows::hann_window(LEN),
+        slide_size,
+        processor,
+    );
+    transform(LEN, slide_size, |b| driver.process_to_vec(b), &buf)
+}
+
+/// `drive()` through a closure, which processes the full spectrum.
+fn drive_full<P: SpectralProcessor<f64>>(mut processor: P) -> Vec<f64> {
+    drive(move |spectrum: &mut [Complex<f64>]| processor.process(spectrum))
+}
+
+#[test]
+fn half_spectrum_processing_matches_full() {
+    let slide_size = LEN / 4;
+    for pitch in [0.8, 1.2] {
+        let processor = PitchShiftProcessor::new(LEN, slide_size, pitch);
+        assert_close(&drive(processor.clone()), &drive_full(processor));
+
+        let processor = VoiceChange::new(LEN, slide_size, 16, 1.1, pitch);
+        assert_close(&drive(processor.clone()), &drive_full(processor));
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::noise;
+use voiche::{
+    api,
+    fft::Fft,
+    num_complex::Complex,
+    spectral::SpectralDriver,
+    time_stretch::TimeStretcher,
+    transform::transform,
+    windows::{self, WindowError},
+};
+
+const WINDOW_SIZE: usize = 1024;
+
+#[test]
+fn identity_is_exact() {
+    let buf = noise(WINDOW_SIZE * 8, 1);
+    let hann = windows::hann_window::<f64>(WINDOW_SIZE);
+    for (post_window, slide_size) in [
+        (
+            windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - WINDOW_SIZE / 4),
+            WINDOW_SIZE / 4,
+        ),
+        (
+            windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE / 4),
+            WINDOW_SIZE / 4,
+        ),
+        (hann.clone(), WINDOW_SIZE / 2),
+        (windows::rectangular_window(WINDOW_SIZE), WINDOW_SIZE / 2),
+    ] {
+        let mut driver = SpectralDriver::new(
+            hann.clone(),
+            post_window,
+            slide_size,
+            |_: &mut [Complex<f64>]| {},
+        );
+        let output = transform(WINDOW_SIZE, slide_size, |b| driver.process_to_vec(b), &buf);
+
+        // Away from the edges, where fewer windows overlap.
+        let range = WINDOW_SIZE..buf.len() - WINDOW_SIZE;
+        assert!(output[range.clone()]
+            .iter()
+            .zip(&buf[range])
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+}
+
+#[test]
+#[should_panic(expected = "can't be reconstructed")]
+fn gap_is_rejected() {
+    let hann = windows::hann_window::<f64>(WINDOW_SIZE);
+    SpectralDriver::new(hann.clone(), hann, WINDOW_SIZE, |_: &mut [Complex<f64>]| {});
+}
+
+#[test]
+fn try_new_returns_the_error() {
+    let hann = windows::hann_window::<f64>(WINDOW_SIZE);
+    let driver = SpectralDriver::try_new(
+        hann.clone(),
+        hann.clone(),
+        WINDOW_SIZE,
+        |_: &mut [Complex<f64>]| {},
+    );
+    assert_eq!(driver.err(), Some(WindowError::Gap { position: 0 }));
+    assert_eq!(
+        TimeStretcher::try_new(hann.clone(), hann[1..].to_vec(), WINDOW_SIZE / 4, 1.0).err(),
+        Some(WindowError::LengthMismatch {
+            pre: WINDOW_SIZE,
+            post: WINDOW_SIZE - 1
+        })
+    );
+    assert!(TimeStretcher::try_new(hann.clone(), hann.clone(), WINDOW_SIZE / 4, 1.0).is_ok());
+    // `time_rate` is not a window error, so it is left to `process()`.
+    assert!(TimeStretcher::try_new(hann.clone(), hann.clone(), WINDOW_SIZE / 4, 0.0).is_ok());
+
+    let fft = Fft::new(WINDOW_SIZE);
+    let buf = vec![0.0; WINDOW_SIZE];
+    let output = api::try_retouch_spectrum(&fft, &hann, &hann, WINDOW_SIZE + 1, &buf, |_| {});
+    assert_eq!(
+        output.err(),
+        Some(WindowError::InvalidSlide {
+            slide_size: WINDOW_SIZE + 1,
+            window_size: WINDOW_SIZE
+        })
+    );
+}
+
+#[test]
+#[should_panic(expected = "is not within")]
+fn time_stretch_rejects_a_long_slide() {
+    let hann = windows::hann_window::<f64>(WINDOW_SIZE);
+    api::time_stretch(hann.clone(), hann, WINDOW_SIZE + 1, 1.0, &[0.0; 16]);
+}
@@ -1,12 +1,67 @@
 use nih_plug::prelude::*;
 use nih_plug_egui::{create_egui_editor, egui, widgets, EguiState};
-use std::sync::{Arc, Mutex};
-use voiche::{api, transform::Transformer, windows};
+use std::sync::Arc;
+use voiche::{
+    auto_tune::AutoTune, pitch_detection::Mpm, pitch_tracker::PitchTracker,
+    spectral::SpectralDriver, transform::RingTransformer, voice_change::VoiceChange, windows,
+};
+
+const WINDOW_SIZE: usize = 1024;
+const SLIDE_SIZE: usize = WINDOW_SIZE / 4;
+
+/// The chain run on each window: the pitch is tracked and corrected by `auto_tune`,
+/// then shifted along with the formant by `driver`.
+struct Voice {
+    driver: SpectralDriver<f32, VoiceChange<f32>>,
+    tracker: PitchTracker<f32, Mpm<f32>>,
+    auto_tune: AutoTune<f32>,
+    /// Whether `auto_tune` corrects the pitch.
+    correct: bool,
+    /// Pitch ratio applied on top of the correction.
+    pitch: f32,
+}
+
+impl Voice {
+    fn new(sample_rate: u32) -> Self {
+        let pre_window = windows::hann_window(WINDOW_SIZE);
+        let post_window = windows::trapezoid_window(WINDOW_SIZE, WINDOW_SIZE - SLIDE_SIZE);
+        Self {
+            driver: SpectralDriver::new(
+                pre_window,
+                post_window,
+                SLIDE_SIZE,
+                VoiceChange::new(WINDOW_SIZE, SLIDE_SIZE, WINDOW_SIZE / 8, 1.0, 1.0),
+            ),
+            tracker: PitchTracker::with_mpm(WINDOW_SIZE, sample_rate),
+            auto_tune: AutoTune::new(sample_rate, SLIDE_SIZE),
+            correct: false,
+            pitch: 1.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.driver.reset();
+        self.tracker.reset();
+        self.auto_tune.reset();
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let correction = if self.correct {
+            let record = self.tracker.process(input);
+            self.auto_tune.process(&record)
+        } else {
+            1.0
+        };
+        self.driver.processor_mut().pitch = self.pitch * correction;
+        self.driver.process(input, output);
+    }
+}
 
 struct MyPlugin {
     params: Arc<MyPluginParams>,
-    params_: Arc<Mutex<(usize, f32, f32)>>,
-    transformer: Transformer<f32, Box<dyn FnMut(&[f32]) -> Vec<f32> + Send + Sync>>,
+    voice: Voice,
+    /// The windows are processed by `voice` with `RingTransformer::process_with()`.
+    transformer: RingTransformer<f32, fn(&[f32], &mut [f32])>,
 }
 
 #[derive(Params)]
@@ -21,50 +76,21 @@ struct MyPluginParams {
     pitch: FloatParam,
     #[id = "formant"]
     formant: FloatParam,
+
+    #[id = "auto_tune"]
+    auto_tune: BoolParam,
+    /// In milliseconds.
+    #[id = "retune_speed"]
+    retune_speed: FloatParam,
 }
 
 impl Default for MyPlugin {
     fn default() -> Self {
-        let window_size = 1024;
-        let slide_size = window_size / 4;
-        let pre_window = windows::hann_window(window_size);
-        let post_window = windows::trapezoid_window(window_size, slide_size);
-        let params = Arc::new(Mutex::new((window_size / 8, 1.0, 1.0)));
         Self {
             params: Arc::new(MyPluginParams::default()),
-            params_: params.clone(),
-            transformer: voiche::transform::Transformer::new(
-                window_size,
-                slide_size,
-                Box::new({
-                    let fft = voiche::fft::Fft::new(window_size);
-                    let mut pitch_shift = voiche::pitch_shift::pitch_shifter(window_size);
-                    let params = params.clone();
-
-                    move |buf: &[f32]| {
-                        api::retouch_spectrum(
-                            &fft,
-                            &pre_window,
-                            &post_window,
-                            slide_size,
-                            buf,
-                            |spectrum| {
-                                let (envelope_order, pitch, formant) =
-                                    params.lock().unwrap().clone();
-                                voiche::voice_change::process_spectrum(
-                                    slide_size,
-                                    &fft,
-                                    &mut pitch_shift,
-                                    envelope_order,
-                                    formant,
-                                    pitch,
-                                    spectrum,
-                                );
-                            },
-                        )
-                    }
-                }),
-            ),
+            // Rebuilt for the actual sample rate in `initialize()`.
+            voice: Voice::new(44100),
+            transformer: RingTransformer::new(WINDOW_SIZE, SLIDE_SIZE, 1, |_, _| unreachable!()),
         }
     }
 }
@@ -72,7 +98,7 @@ impl Default for MyPlugin {
 impl Default for MyPluginParams {
     fn default() -> Self {
         Self {
-            editor_state: EguiState::from_size(320, 220),
+            editor_state: EguiState::from_size(320, 320),
 
             gain: FloatParam::new(
                 "Gain",
@@ -103,6 +129,16 @@ impl Default for MyPluginParams {
                     max: 4.0,
                 },
             ),
+            auto_tune: BoolParam::new("Auto-tune", false),
+            retune_speed: FloatParam::new(
+                "Retune speed",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 200.0,
+                },
+            )
+            .with_unit(" ms"),
         }
     }
 }
@@ -125,7 +161,7 @@ impl Plugin for MyPlugin {
         names: PortNames::const_default(),
     }];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -155,6 +191,13 @@ impl Plugin for MyPlugin {
                     ui.add(widgets::ParamSlider::for_param(&params.pitch, setter));
                     ui.label("Formant");
                     ui.add(widgets::ParamSlider::for_param(&params.formant, setter));
+                    ui.label("Auto-tune (to the held MIDI notes, or the chromatic scale)");
+                    ui.add(widgets::ParamSlider::for_param(&params.auto_tune, setter));
+                    ui.label("Retune speed");
+                    ui.add(widgets::ParamSlider::for_param(
+                        &params.retune_speed,
+                        setter,
+                    ));
                 });
             },
         )
@@ -163,18 +206,21 @@ impl Plugin for MyPlugin {
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
+        buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
+        self.voice = Voice::new(buffer_config.sample_rate as u32);
         true
     }
 
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.voice.reset();
+        self.transformer.reset();
     }
 
     fn process(
@@ -185,19 +231,34 @@ impl Plugin for MyPlugin {
     ) -> ProcessStatus {
         // let sample_rate = context.transport().sample_rate;
 
-        for channel_samples in buffer.iter_samples() {
+        let mut next_event = context.next_event();
+        for (sample_id, channel_samples) in buffer.iter_samples().enumerate() {
             let gain = self.params.gain.smoothed.next();
             let pitch = self.params.pitch.smoothed.next();
             let formant = self.params.formant.smoothed.next();
-            {
-                let mut params = self.params_.lock().unwrap();
-                *params = (params.0, pitch, formant);
+            let voice = &mut self.voice;
+            // Hold the notes from their sample offset on.
+            while let Some(event) = next_event {
+                if sample_id < event.timing() as usize {
+                    break;
+                }
+                match event {
+                    NoteEvent::NoteOn { note, .. } => voice.auto_tune.note_on(note),
+                    NoteEvent::NoteOff { note, .. } => voice.auto_tune.note_off(note),
+                    _ => (),
+                }
+                next_event = context.next_event();
             }
+            voice.correct = self.params.auto_tune.value();
+            voice.auto_tune.retune_speed = self.params.retune_speed.value();
+            voice.pitch = pitch;
+            voice.driver.processor_mut().formant = formant;
 
             for sample in channel_samples {
                 let mut buf = [*sample];
-                self.transformer.input_slice(&mut buf);
-                self.transformer.process();
+                self.transformer.input_slice(&buf);
+                self.transformer
+                    .process_with(|input, output| voice.process(input, output));
                 if !self.transformer.output_slice_exact(&mut buf) {
                     buf.fill(0.0);
                 }
@@ -216,7 +277,7 @@ impl ClapPlugin for MyPlugin {
     const CLAP_SUPPORT_URL: Option<&'static str> = None;
 
     // Don't forget to change these features
-    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Stereo];
+    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Mono];
 }
 
 impl Vst3Plugin for MyPlugin {